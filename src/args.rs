@@ -1,13 +1,33 @@
-use clap::Parser;
+use crate::comments::CommentMode;
+use crate::compact::IndentMode;
+use crate::config::{ConfigLayer, ListValue};
+use crate::git::{ChangeScope, DiffMode};
+use crate::license::LicenseMode;
+use crate::output::OutputFormat;
+use crate::relevance::{Query, QueryOrder};
+use crate::toc::TocStyle;
+use crate::tokens::Tokenizer;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
-#[command(name = "ctxsnap", version, about = "Concatenates project files into a single Markdown snapshot.", long_about = None)]
+#[command(name = "ctxsnap", version, about = "Concatenates project files into a single Markdown, JSON or XML snapshot.", long_about = None)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Root directory to scan. Defaults to current directory.
     #[arg(default_value = ".")]
     pub root: PathBuf,
 
+    /// Global config file. Overrides $CTXSNAP_CONFIG and the XDG config location.
+    #[arg(long, value_name = "PATH", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Apply a `[profile.<name>]` table from the config files.
+    #[arg(long, short = 'p', value_name = "NAME")]
+    pub profile: Option<String>,
+
     /// Actually run the snapshot generation. Without this, shows help.
     #[arg(long, short = 'r')]
     pub run: bool,
@@ -16,6 +36,42 @@ pub struct Args {
     #[arg(long, short = 'o')]
     pub output: Option<PathBuf>,
 
+    /// Output document format.
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Table of contents layout: a flat list, or a directory tree with per-directory
+    /// file counts, sizes and tokens.
+    #[arg(long, value_enum, value_name = "STYLE")]
+    pub toc: Option<TocStyle>,
+
+    /// With a tree TOC, also list omitted files, marked with the reason.
+    #[arg(long)]
+    pub toc_omitted: bool,
+
+    /// With a tree TOC, collapse directories this many levels below the root.
+    #[arg(long, value_name = "N")]
+    pub toc_depth: Option<usize>,
+
+    /// Rank files by relevance to this natural-language query and fill the
+    /// budgets with the highest-scoring files first.
+    #[arg(long, value_name = "TEXT")]
+    pub query: Option<String>,
+
+    /// Order of the files in a --query snapshot.
+    #[arg(long, value_enum, value_name = "ORDER")]
+    pub query_order: Option<QueryOrder>,
+
+    /// Entry point whose transitive imports form the snapshot (repeatable). Relative paths
+    /// are taken from the root directory. Follows Rust `mod`/`use`, Python `import`/`from`,
+    /// JS/TS `import`/`require` and C/C++ `#include` within the repository.
+    #[arg(long, value_name = "PATH")]
+    pub seed: Vec<PathBuf>,
+
+    /// With --seed, follow imports at most this many hops from the seeds.
+    #[arg(long, value_name = "N", requires = "seed")]
+    pub seed_depth: Option<usize>,
+
     /// Force overwrite if the output file already exists.
     #[arg(long)]
     pub force: bool,
@@ -28,14 +84,40 @@ pub struct Args {
     #[arg(long)]
     pub max_file_mb: Option<u64>,
 
-    /// Maximum total size of included content in MB.
+    /// Maximum total size of included content in MB. Not applied with --part-max-mb or
+    /// --part-max-tokens, which spread every file over as many parts as needed.
     #[arg(long)]
     pub max_total_mb: Option<u64>,
 
+    /// Maximum tokens per file, counting any `--diff` output. Files with more tokens are skipped.
+    #[arg(long)]
+    pub max_file_tokens: Option<u64>,
+
+    /// Maximum total tokens of included content. Not applied with --part-max-mb or
+    /// --part-max-tokens, which spread every file over as many parts as needed.
+    #[arg(long)]
+    pub max_total_tokens: Option<u64>,
+
+    /// Tokenizer profile used for token counts and budgets.
+    #[arg(long, value_enum)]
+    pub tokenizer: Option<Tokenizer>,
+
+    /// Split the output into parts of at most this many MB, plus an index.
+    #[arg(long)]
+    pub part_max_mb: Option<u64>,
+
+    /// Split the output into parts of at most this many tokens, plus an index.
+    #[arg(long)]
+    pub part_max_tokens: Option<u64>,
+
     /// Disable .gitignore usage.
     #[arg(long)]
     pub no_gitignore: bool,
 
+    /// Disable .ctxsnapignore usage.
+    #[arg(long)]
+    pub no_ctxsnapignore: bool,
+
     /// Include lock files (Cargo.lock, package-lock.json, etc.).
     #[arg(long)]
     pub include_lockfiles: bool,
@@ -52,32 +134,146 @@ pub struct Args {
     #[arg(long, value_delimiter = ',')]
     pub exclude_file: Vec<String>,
 
-    /// Remove comments from supported file types.
+    /// Gitignore-style glob of files to include (repeatable). Prefix with `!` to negate.
+    /// Includes that spell out a path, file name or extension re-admit the files they
+    /// match that the default exclusion lists drop.
+    #[arg(long)]
+    pub include: Vec<String>,
+
+    /// Gitignore-style glob of files or directories to exclude (repeatable).
+    /// Prefix with `!` to negate.
+    #[arg(long)]
+    pub exclude: Vec<String>,
+
+    /// Snapshot a git revision (commit, branch or tag) read from the object database,
+    /// without checking it out.
+    #[arg(long, value_name = "REF", conflicts_with_all = ["since", "staged", "unstaged"])]
+    pub rev: Option<String>,
+
+    /// Only snapshot files changed since the merge base of this git ref and HEAD,
+    /// including uncommitted and untracked changes.
+    #[arg(long, value_name = "REF", conflicts_with_all = ["staged", "unstaged"])]
+    pub since: Option<String>,
+
+    /// Only snapshot files with staged changes.
+    #[arg(long, conflicts_with = "unstaged")]
+    pub staged: bool,
+
+    /// Only snapshot files with unstaged changes, including untracked files.
+    #[arg(long)]
+    pub unstaged: bool,
+
+    /// Emit the unified diff of each changed file (requires --since, --staged or --unstaged).
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub diff: Option<DiffMode>,
+
+    /// Add the git HEAD commit, branch, dirty state and recent commits to the header.
+    #[arg(long)]
+    pub git_info: bool,
+
+    /// Add each file's last-modifying commit and author date under its heading.
+    #[arg(long)]
+    pub git_file_info: bool,
+
+    /// Remove comments from supported file types. Same as `--comment-mode all`.
     #[arg(long)]
     pub remove_comments: bool,
 
+    /// Which comments to remove from supported file types.
+    #[arg(long, value_enum, conflicts_with = "remove_comments")]
+    pub comment_mode: Option<CommentMode>,
+
+    /// What to do with license or copyright headers at the top of files.
+    /// Applies regardless of --comment-mode.
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub license_headers: Option<LicenseMode>,
+
+    /// Trim trailing whitespace, collapse runs of blank lines and normalize CRLF to LF.
+    /// String literals and YAML block scalars are left as they are.
+    #[arg(long)]
+    pub compact: bool,
+
+    /// With --compact, rewrite indentation in languages where it carries no meaning.
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub compact_indent: Option<IndentMode>,
+
+    /// Prefix each line with its number in the original file, even after stripping.
+    #[arg(long)]
+    pub line_numbers: bool,
+
+    /// Do not scan file contents for secrets.
+    #[arg(long)]
+    pub no_secret_scan: bool,
+
+    /// Regex of an additional secret to redact (repeatable).
+    /// A capture group named `secret` limits the redaction to that group.
+    #[arg(long, value_name = "REGEX")]
+    pub secret_pattern: Vec<String>,
+
+    /// Abort without writing the snapshot if any secret is found.
+    #[arg(long)]
+    pub fail_on_secrets: bool,
+
     /// Maximum depth to scan.
     #[arg(long)]
     pub depth: Option<usize>,
 
+    /// Number of worker threads for reading and processing files.
+    /// Defaults to the available parallelism.
+    #[arg(long, short = 'j')]
+    pub jobs: Option<usize>,
+
     /// Create a local ctxsnap.toml in the root directory.
     #[arg(long)]
     pub init: bool,
+
+    /// Print the effective merged config and the layer each value came from.
+    #[arg(long)]
+    pub show_config: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Manage config files.
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Write a config file with the default settings.
+    Init {
+        /// Write the global config instead of ctxsnap.toml in the root directory.
+        #[arg(long)]
+        global: bool,
+
+        /// Overwrite an existing config file.
+        #[arg(long)]
+        force: bool,
+    },
 }
 
 impl Args {
     pub fn validate(&self) -> anyhow::Result<()> {
-        if let Some(m) = self.max_file_mb {
-            anyhow::ensure!(m > 0, "max_file_mb must be positive");
-            anyhow::ensure!(m <= 1024, "max_file_mb cannot exceed 1GB");
+        if let Some((_, message)) = self.config_layer().range_error() {
+            anyhow::bail!(message);
         }
-        if let Some(m) = self.max_total_mb {
-            anyhow::ensure!(m > 0, "max_total_mb must be positive");
-            anyhow::ensure!(m <= 10240, "max_total_mb cannot exceed 10GB");
+        if let Some(j) = self.jobs {
+            anyhow::ensure!(j > 0, "jobs must be positive");
         }
-        if let Some(d) = self.depth {
-            anyhow::ensure!(d > 0 && d < 1000, "depth must be between 1 and 999");
+        if let Some(query) = &self.query {
+            anyhow::ensure!(
+                !Query::new(query).is_empty(),
+                "--query has no searchable words: {:?}",
+                query
+            );
         }
+        anyhow::ensure!(
+            self.diff.is_none() || self.change_scope().is_some(),
+            "--diff requires --since, --staged or --unstaged"
+        );
         anyhow::ensure!(
             self.root.exists(),
             "Root path does not exist: {:?}",
@@ -85,4 +281,59 @@ impl Args {
         );
         Ok(())
     }
+
+    /// Settings given on the command line, as the top config layer.
+    /// Lists extend the lower layers.
+    pub fn config_layer(&self) -> ConfigLayer {
+        let list =
+            |items: &[String]| (!items.is_empty()).then(|| ListValue::Extend(items.to_vec()));
+        ConfigLayer {
+            exclude_ext: list(&self.exclude_ext),
+            exclude_dir: list(&self.exclude_dir),
+            exclude_file: list(&self.exclude_file),
+            include: list(&self.include),
+            exclude: list(&self.exclude),
+            max_file_mb: self.max_file_mb,
+            max_total_mb: self.max_total_mb,
+            use_gitignore: self.no_gitignore.then_some(false),
+            use_ctxsnapignore: self.no_ctxsnapignore.then_some(false),
+            include_lockfiles: self.include_lockfiles.then_some(true),
+            comment_mode: self
+                .comment_mode
+                .or(self.remove_comments.then_some(CommentMode::All)),
+            license_headers: self.license_headers,
+            compact: self.compact.then_some(true),
+            compact_indent: self.compact_indent,
+            line_numbers: self.line_numbers.then_some(true),
+            scan_secrets: self.no_secret_scan.then_some(false),
+            secret_patterns: list(&self.secret_pattern),
+            fail_on_secrets: self.fail_on_secrets.then_some(true),
+            depth: self.depth,
+            tokenizer: self.tokenizer,
+            max_file_tokens: self.max_file_tokens,
+            max_total_tokens: self.max_total_tokens,
+            format: self.format,
+            toc: self.toc,
+            toc_omitted: self.toc_omitted.then_some(true),
+            toc_depth: self.toc_depth,
+            query_order: self.query_order,
+            part_max_mb: self.part_max_mb,
+            part_max_tokens: self.part_max_tokens,
+            git_info: self.git_info.then_some(true),
+            git_file_info: self.git_file_info.then_some(true),
+        }
+    }
+
+    /// Git change selection requested on the command line, if any.
+    pub fn change_scope(&self) -> Option<ChangeScope> {
+        if let Some(rev) = &self.since {
+            Some(ChangeScope::Since(rev.clone()))
+        } else if self.staged {
+            Some(ChangeScope::Staged)
+        } else if self.unstaged {
+            Some(ChangeScope::Unstaged)
+        } else {
+            None
+        }
+    }
 }
@@ -0,0 +1,1005 @@
+use crate::lines;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Which comments are removed from file contents.
+#[derive(Serialize, Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommentMode {
+    /// Keep every comment.
+    #[default]
+    None,
+    /// Remove every comment.
+    All,
+    /// Remove comments but keep doc comments (`///`, `/** */`, Go declaration comments).
+    NonDoc,
+    /// Remove only a license or copyright block at the top of the file.
+    LicenseHeadersOnly,
+}
+
+/// How a quote character can appear inside its own string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Escape {
+    /// `\"` (most languages).
+    Backslash,
+    /// `` `" `` (PowerShell).
+    Backtick,
+    /// `''` (SQL, YAML single quotes).
+    Doubled,
+    /// No escapes (shell single quotes, TOML literal strings).
+    None,
+}
+
+/// What a backtick starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backticks {
+    None,
+    /// Raw string up to the next backtick (Go).
+    Raw,
+    /// Template literal with `${ ... }` code inside (JavaScript, TypeScript).
+    Template,
+}
+
+/// How heredoc bodies are introduced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Heredocs {
+    None,
+    /// `<<EOF`, `<<-EOF`, `<< 'EOF'`.
+    Shell,
+    /// `<<~EOS`, `<<"EOF"`, `<<<EOT` (Ruby, Perl, PHP).
+    Script,
+}
+
+/// Comment and string grammar of a language, enough to tell comments from code.
+#[derive(Debug)]
+pub struct Syntax {
+    /// Line comment markers.
+    pub line: &'static [&'static str],
+    /// Block comment delimiters.
+    pub block: Option<(&'static str, &'static str)>,
+    /// Block comments nest (Rust, Swift, Kotlin, Haskell).
+    pub nested: bool,
+    /// Block delimiters only count at the start of a line (Ruby `=begin`/`=end`).
+    pub anchored_block: bool,
+    /// Line markers only count at the start of a word (`#` in shell and YAML).
+    pub word_start: bool,
+    /// Comment-like prefixes that are code (`#[` attributes in PHP, `{-#` pragmas in Haskell).
+    pub not_comment: &'static [&'static str],
+    /// `--` is a comment only when not part of an operator such as `-->` (Haskell).
+    pub operator_dashes: bool,
+    /// String quote characters and their escape rule.
+    pub quotes: &'static [(u8, Escape)],
+    /// Strings may span lines; otherwise an unterminated string ends at the newline.
+    pub multiline_strings: bool,
+    /// `"""` and `'''` strings for the quote characters above.
+    pub triple_quotes: bool,
+    /// `'` inside a word is part of it (`foldl'`, `1'000`, `don't`), not a quote.
+    pub word_apostrophe: bool,
+    /// Fixed-delimiter strings such as `<![CDATA[ ... ]]>`.
+    pub delimited: &'static [(&'static str, &'static str)],
+    pub backticks: Backticks,
+    pub heredocs: Heredocs,
+    /// Raw strings, byte literals and lifetimes (Rust).
+    pub rust_literals: bool,
+    /// `R"delim( ... )delim"` (C++).
+    pub cpp_raw_strings: bool,
+    /// `@"..."` with doubled quotes (C#).
+    pub verbatim_strings: bool,
+    /// `/regex/` literals (JavaScript, TypeScript).
+    pub regex_literals: bool,
+    /// `[[ ... ]]` strings and `--[[ ... ]]` comments with `=` levels (Lua).
+    pub long_brackets: bool,
+    /// Prefixes of doc comments. `///` does not match `////`.
+    pub doc: &'static [&'static str],
+    /// Keywords that make the comment lines directly above a declaration its docs (Go).
+    pub doc_before: &'static [&'static str],
+}
+
+const BASE: Syntax = Syntax {
+    line: &[],
+    block: None,
+    nested: false,
+    anchored_block: false,
+    word_start: false,
+    not_comment: &[],
+    operator_dashes: false,
+    quotes: &[(b'"', Escape::Backslash), (b'\'', Escape::Backslash)],
+    multiline_strings: false,
+    triple_quotes: false,
+    word_apostrophe: false,
+    delimited: &[],
+    backticks: Backticks::None,
+    heredocs: Heredocs::None,
+    rust_literals: false,
+    cpp_raw_strings: false,
+    verbatim_strings: false,
+    regex_literals: false,
+    long_brackets: false,
+    doc: &[],
+    doc_before: &[],
+};
+
+// Style families, used for extensions without a dedicated entry
+
+const C_FAMILY: Syntax = Syntax {
+    line: &["//"],
+    block: Some(("/*", "*/")),
+    doc: &["///", "//!", "/**", "/*!"],
+    ..BASE
+};
+
+const HASH_FAMILY: Syntax = Syntax {
+    line: &["#"],
+    ..BASE
+};
+
+const DASH_FAMILY: Syntax = Syntax {
+    line: &["--"],
+    ..BASE
+};
+
+const XML_FAMILY: Syntax = Syntax {
+    block: Some(("<!--", "-->")),
+    quotes: &[],
+    delimited: &[("<![CDATA[", "]]>")],
+    ..BASE
+};
+
+// Languages
+
+const RUST: Syntax = Syntax {
+    nested: true,
+    quotes: &[(b'"', Escape::Backslash)],
+    multiline_strings: true,
+    rust_literals: true,
+    ..C_FAMILY
+};
+
+const C: Syntax = Syntax {
+    word_apostrophe: true,
+    ..C_FAMILY
+};
+
+const CPP: Syntax = Syntax {
+    cpp_raw_strings: true,
+    ..C
+};
+
+const CSHARP: Syntax = Syntax {
+    triple_quotes: true,
+    verbatim_strings: true,
+    ..C_FAMILY
+};
+
+const GO: Syntax = Syntax {
+    backticks: Backticks::Raw,
+    doc: &[],
+    doc_before: &["package", "func", "type", "var", "const"],
+    ..C_FAMILY
+};
+
+const JAVA: Syntax = Syntax {
+    triple_quotes: true,
+    ..C_FAMILY
+};
+
+const KOTLIN: Syntax = Syntax {
+    nested: true,
+    ..JAVA
+};
+
+const JAVASCRIPT: Syntax = Syntax {
+    backticks: Backticks::Template,
+    regex_literals: true,
+    ..C_FAMILY
+};
+
+const CSS: Syntax = Syntax {
+    line: &[],
+    ..C_FAMILY
+};
+
+const PHP: Syntax = Syntax {
+    line: &["//", "#"],
+    not_comment: &["#["],
+    multiline_strings: true,
+    heredocs: Heredocs::Script,
+    ..C_FAMILY
+};
+
+// Docstrings are string literals and always kept; `#:` documents attributes
+const PYTHON: Syntax = Syntax {
+    triple_quotes: true,
+    doc: &["#:"],
+    ..HASH_FAMILY
+};
+
+const SHELL: Syntax = Syntax {
+    word_start: true,
+    quotes: &[(b'"', Escape::Backslash), (b'\'', Escape::None)],
+    multiline_strings: true,
+    heredocs: Heredocs::Shell,
+    ..HASH_FAMILY
+};
+
+const RUBY: Syntax = Syntax {
+    block: Some(("=begin", "=end")),
+    anchored_block: true,
+    multiline_strings: true,
+    heredocs: Heredocs::Script,
+    ..HASH_FAMILY
+};
+
+const PERL: Syntax = Syntax {
+    word_start: true,
+    multiline_strings: true,
+    heredocs: Heredocs::Script,
+    ..HASH_FAMILY
+};
+
+const POWERSHELL: Syntax = Syntax {
+    block: Some(("<#", "#>")),
+    quotes: &[(b'"', Escape::Backtick), (b'\'', Escape::Doubled)],
+    multiline_strings: true,
+    delimited: &[("@\"", "\"@"), ("@'", "'@")],
+    ..HASH_FAMILY
+};
+
+const YAML: Syntax = Syntax {
+    word_start: true,
+    quotes: &[(b'"', Escape::Backslash), (b'\'', Escape::Doubled)],
+    multiline_strings: true,
+    word_apostrophe: true,
+    ..HASH_FAMILY
+};
+
+const TOML: Syntax = Syntax {
+    quotes: &[(b'"', Escape::Backslash), (b'\'', Escape::None)],
+    triple_quotes: true,
+    ..HASH_FAMILY
+};
+
+const SQL: Syntax = Syntax {
+    block: Some(("/*", "*/")),
+    quotes: &[(b'\'', Escape::Doubled), (b'"', Escape::Doubled)],
+    multiline_strings: true,
+    ..DASH_FAMILY
+};
+
+const LUA: Syntax = Syntax {
+    long_brackets: true,
+    doc: &["---"],
+    ..DASH_FAMILY
+};
+
+const HASKELL: Syntax = Syntax {
+    block: Some(("{-", "-}")),
+    nested: true,
+    not_comment: &["{-#"],
+    operator_dashes: true,
+    word_apostrophe: true,
+    doc: &["-- |", "-- ^", "-- $", "{- |"],
+    ..DASH_FAMILY
+};
+
+/// Comment syntax for a language from `language::detect`, or `None` when comments are left alone.
+pub fn syntax_for(language: &str) -> Option<&'static Syntax> {
+    let syntax = match language {
+        "rust" => &RUST,
+        "c" => &C,
+        "cpp" => &CPP,
+        "csharp" => &CSHARP,
+        "go" => &GO,
+        "java" => &JAVA,
+        "kotlin" | "swift" | "scala" => &KOTLIN,
+        "javascript" | "jsx" | "typescript" | "tsx" => &JAVASCRIPT,
+        "css" => &CSS,
+        "php" => &PHP,
+        "python" => &PYTHON,
+        "bash" | "zsh" | "dockerfile" => &SHELL,
+        "ruby" => &RUBY,
+        "perl" => &PERL,
+        "powershell" => &POWERSHELL,
+        "yaml" => &YAML,
+        "toml" => &TOML,
+        "sql" => &SQL,
+        "lua" => &LUA,
+        "haskell" => &HASKELL,
+        "scss" | "less" | "dart" | "groovy" | "protobuf" => &C_FAMILY,
+        "r" | "cmake" | "nim" | "makefile" => &HASH_FAMILY,
+        "ada" | "vhdl" => &DASH_FAMILY,
+        "html" | "xml" | "vue" | "svelte" => &XML_FAMILY,
+        _ => return None,
+    };
+    Some(syntax)
+}
+
+/// Removes the comments selected by `mode` without touching code or string contents.
+/// Also returns the indices of the lines that were kept.
+pub fn strip(content: &str, syntax: &Syntax, mode: CommentMode) -> (String, Vec<usize>) {
+    let mut comments = find_comments(content, syntax);
+    match mode {
+        CommentMode::None => comments.clear(),
+        CommentMode::All => {}
+        CommentMode::NonDoc => comments.retain(|range| !is_doc(content, range, syntax)),
+        CommentMode::LicenseHeadersOnly => {
+            comments = leading_blocks(content, comments, syntax)
+                .into_iter()
+                .take_while(|block| crate::license::has_marker(&block_text(content, block)))
+                .flatten()
+                .collect()
+        }
+    }
+    remove(content, comments)
+}
+
+/// Removes the given comment ranges, which must be sorted and come from `find_comments`.
+/// Lines that held only comments are dropped; other lines keep their place.
+/// Also returns the indices of the lines that were kept.
+pub fn remove(content: &str, comments: Vec<Range<usize>>) -> (String, Vec<usize>) {
+    if comments.is_empty() {
+        return (content.to_string(), (0..lines::count(content)).collect());
+    }
+
+    let mut out = String::with_capacity(content.len());
+    // Lines (by index in `content`) that lost a comment; dropped if nothing else is left
+    let mut touched = Vec::new();
+    let mut line = 0;
+    let mut pos = 0;
+    for range in comments {
+        out.push_str(&content[pos..range.start]);
+        line += content[pos..range.start].matches('\n').count();
+
+        let text = &content[range.clone()];
+        let rest = &content[range.end..];
+        let newlines = text.matches('\n').count();
+        touched.extend(line..=line + newlines);
+        line += newlines;
+
+        let ends_line = rest.is_empty() || rest.starts_with('\n') || rest.starts_with("\r\n");
+        let space_follows = rest.starts_with([' ', '\t']);
+        // Only indentation before the comment: keep it and drop the space after instead,
+        // which matters in layout-sensitive languages
+        let indent_only = newlines == 0
+            && out[out.rfind('\n').map_or(0, |at| at + 1)..]
+                .chars()
+                .all(|c| c == ' ' || c == '\t');
+        if ends_line || newlines > 0 || (space_follows && !indent_only) {
+            out.truncate(out.trim_end_matches([' ', '\t']).len());
+        }
+        if newlines > 0 {
+            let line_break = if text.contains("\r\n") { "\r\n" } else { "\n" };
+            out.push_str(&line_break.repeat(newlines));
+        } else if !ends_line {
+            // A comment can separate tokens: `a/**/b` must not become `ab`
+            let before = out.chars().next_back().is_some_and(|c| !c.is_whitespace());
+            let after = rest.chars().next().is_some_and(|c| !c.is_whitespace());
+            if before && after {
+                out.push(' ');
+            }
+        }
+        pos = range.end;
+        if space_follows && indent_only {
+            pos += rest.len() - rest.trim_start_matches([' ', '\t']).len();
+        }
+    }
+    out.push_str(&content[pos..]);
+
+    let mut result = String::with_capacity(out.len());
+    let mut kept = Vec::new();
+    let mut touched = touched.into_iter().peekable();
+    for (idx, text) in out.split_inclusive('\n').enumerate() {
+        while touched.next_if(|&t| t < idx).is_some() {}
+        let was_touched = touched.peek() == Some(&idx);
+        if !(was_touched && text.trim().is_empty()) {
+            result.push_str(text);
+            kept.push(idx);
+        }
+    }
+    (result, kept)
+}
+
+fn is_doc(content: &str, range: &Range<usize>, syntax: &Syntax) -> bool {
+    let text = &content[range.clone()];
+    let prefixed = syntax.doc.iter().any(|prefix| {
+        // `////` and `/***` are decoration, not docs
+        let repeated = prefix.chars().next_back().unwrap_or(' ');
+        text.starts_with(prefix) && !text[prefix.len()..].starts_with(repeated)
+    });
+    if prefixed {
+        return text != "/**/";
+    }
+    if syntax.doc_before.is_empty() {
+        return false;
+    }
+
+    // The comment must start its line and lead, possibly with more comment lines, to a declaration
+    let line_start = content[..range.start].rfind('\n').map_or(0, |i| i + 1);
+    if !content[line_start..range.start].trim().is_empty() {
+        return false;
+    }
+    let mut rest = &content[range.end..];
+    while let Some(newline) = rest.find('\n') {
+        rest = &rest[newline + 1..];
+        let line = rest.lines().next().unwrap_or("").trim_start();
+        if line.starts_with("//") {
+            continue;
+        }
+        return syntax.doc_before.iter().any(|keyword| {
+            line.strip_prefix(keyword)
+                .is_some_and(|after| after.starts_with([' ', '\t', '(']))
+        });
+    }
+    false
+}
+
+/// Comment blocks at the top of the file, after any `#!` line, up to the first code or doc comment.
+/// Blocks are separated by blank lines.
+pub fn leading_blocks(
+    content: &str,
+    comments: Vec<Range<usize>>,
+    syntax: &Syntax,
+) -> Vec<Vec<Range<usize>>> {
+    let mut pos = if content.starts_with("#!") {
+        content.find('\n').unwrap_or(content.len())
+    } else {
+        0
+    };
+    let mut blocks: Vec<Vec<Range<usize>>> = Vec::new();
+    for range in comments {
+        let gap = &content[pos..range.start];
+        if !gap.trim().is_empty() || is_doc(content, &range, syntax) {
+            break;
+        }
+        match blocks.last_mut() {
+            Some(block) if gap.matches('\n').count() < 2 => block.push(range.clone()),
+            _ => blocks.push(vec![range.clone()]),
+        }
+        pos = range.end;
+    }
+    blocks
+}
+
+/// The comment text of a block, one comment per line.
+pub fn block_text(content: &str, block: &[Range<usize>]) -> String {
+    block
+        .iter()
+        .map(|r| &content[r.clone()])
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Byte ranges of every comment in `content`, in order.
+/// Line comments end before the line break.
+pub fn find_comments(content: &str, syntax: &Syntax) -> Vec<Range<usize>> {
+    lex(content, syntax).comments
+}
+
+/// Byte ranges of string literals and heredoc bodies that span more than one line.
+/// Ranges can nest, e.g. a string inside a template literal expression.
+pub fn find_multiline_literals(content: &str, syntax: &Syntax) -> Vec<Range<usize>> {
+    lex(content, syntax).literals
+}
+
+fn lex<'a>(content: &'a str, syntax: &'a Syntax) -> Lexer<'a> {
+    let mut lexer = Lexer {
+        src: content,
+        bytes: content.as_bytes(),
+        pos: 0,
+        syntax,
+        comments: Vec::new(),
+        literals: Vec::new(),
+        heredocs: Vec::new(),
+    };
+    // A `#!` interpreter line is kept even where `#` starts comments
+    if content.starts_with("#!") {
+        lexer.pos = lexer.end_of_line(0);
+    }
+    lexer.run(false);
+    lexer
+}
+
+struct Lexer<'a> {
+    src: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    syntax: &'a Syntax,
+    comments: Vec<Range<usize>>,
+    literals: Vec<Range<usize>>,
+    // Heredoc terminators whose bodies start after the current line
+    heredocs: Vec<String>,
+}
+
+impl Lexer<'_> {
+    /// Scans code up to the end of input, or past the `}` closing a template
+    /// expression when `in_braces` is set.
+    fn run(&mut self, in_braces: bool) {
+        let mut depth = 0usize;
+        while self.pos < self.bytes.len() {
+            let c = self.bytes[self.pos];
+            if c == b'\n' {
+                self.pos += 1;
+                if !self.heredocs.is_empty() {
+                    let start = self.pos;
+                    self.skip_heredoc_bodies();
+                    self.literals.push(start..self.pos);
+                }
+                continue;
+            }
+            if self.comment() {
+                continue;
+            }
+            let start = self.pos;
+            if self.string() {
+                if self.src[start..self.pos].contains('\n') {
+                    self.literals.push(start..self.pos);
+                }
+                continue;
+            }
+            if in_braces {
+                match c {
+                    b'{' => depth += 1,
+                    b'}' if depth == 0 => {
+                        self.pos += 1;
+                        return;
+                    }
+                    b'}' => depth -= 1,
+                    _ => {}
+                }
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        self.bytes[self.pos..].starts_with(s.as_bytes())
+    }
+
+    fn peek(&self, offset: usize) -> Option<u8> {
+        self.bytes.get(self.pos + offset).copied()
+    }
+
+    fn prev(&self) -> Option<u8> {
+        self.pos.checked_sub(1).map(|p| self.bytes[p])
+    }
+
+    fn after_word_char(&self) -> bool {
+        self.prev().is_some_and(is_word_byte)
+    }
+
+    fn find(&self, from: usize, needle: &str) -> Option<usize> {
+        self.src[from..].find(needle).map(|i| from + i)
+    }
+
+    fn end_of_line(&self, from: usize) -> usize {
+        let end = self.find(from, "\n").unwrap_or(self.bytes.len());
+        if end > from && self.bytes[end - 1] == b'\r' {
+            end - 1
+        } else {
+            end
+        }
+    }
+
+    fn comment(&mut self) -> bool {
+        let start = self.pos;
+        let syntax = self.syntax;
+
+        if syntax.not_comment.iter().any(|p| self.starts_with(p)) {
+            return false;
+        }
+
+        if let Some((open, close)) = syntax.block {
+            let at_line_start = start == 0 || self.bytes[start - 1] == b'\n';
+            if self.starts_with(open) && (!syntax.anchored_block || at_line_start) {
+                self.skip_block(open, close);
+                self.comments.push(start..self.pos);
+                return true;
+            }
+        }
+
+        if syntax.long_brackets && self.starts_with("--[") {
+            if let Some(level) = self.long_bracket_level(start + 2) {
+                self.pos = self.long_bracket_end(start + 2, level);
+                self.comments.push(start..self.pos);
+                return true;
+            }
+        }
+
+        for marker in syntax.line {
+            if !self.starts_with(marker) {
+                continue;
+            }
+            if syntax.word_start
+                && !matches!(
+                    self.prev(),
+                    None | Some(b' ' | b'\t' | b'\n' | b'\r' | b';' | b'&' | b'|' | b'(' | b')')
+                )
+            {
+                continue;
+            }
+            if syntax.operator_dashes && !self.dashes_start_comment() {
+                continue;
+            }
+            self.pos = self.end_of_line(start);
+            self.comments.push(start..self.pos);
+            return true;
+        }
+        false
+    }
+
+    fn skip_block(&mut self, open: &str, close: &str) {
+        let len = self.bytes.len();
+        self.pos += open.len();
+        let mut depth = 1;
+        while self.pos < len {
+            if self.syntax.nested && self.starts_with(open) {
+                depth += 1;
+                self.pos += open.len();
+            } else if self.starts_with(close)
+                && (!self.syntax.anchored_block || self.bytes[self.pos - 1] == b'\n')
+            {
+                self.pos += close.len();
+                depth -= 1;
+                if depth == 0 {
+                    if self.syntax.anchored_block {
+                        self.pos = self.end_of_line(self.pos);
+                    }
+                    return;
+                }
+            } else {
+                self.pos += 1;
+            }
+        }
+    }
+
+    /// `--` followed by more dashes and then a symbol is an operator in Haskell.
+    fn dashes_start_comment(&self) -> bool {
+        const SYMBOLS: &[u8] = b"!#$%&*+./<=>?@\\^|~:";
+        let mut end = self.pos;
+        while self.bytes.get(end) == Some(&b'-') {
+            end += 1;
+        }
+        let before = self.prev().is_some_and(|b| SYMBOLS.contains(&b));
+        let after = self.bytes.get(end).is_some_and(|b| SYMBOLS.contains(b));
+        !before && !after
+    }
+
+    fn string(&mut self) -> bool {
+        let syntax = self.syntax;
+        let c = self.bytes[self.pos];
+
+        for (open, close) in syntax.delimited {
+            if self.starts_with(open) {
+                self.pos = self
+                    .find(self.pos + open.len(), close)
+                    .map_or(self.bytes.len(), |i| i + close.len());
+                return true;
+            }
+        }
+        if syntax.rust_literals && self.rust_literal() {
+            return true;
+        }
+        if syntax.cpp_raw_strings && c == b'"' && self.cpp_raw_string() {
+            return true;
+        }
+        if syntax.verbatim_strings && c == b'@' && self.peek(1) == Some(b'"') {
+            self.pos += 1;
+            self.quoted(b'"', Escape::Doubled, true);
+            return true;
+        }
+        if syntax.long_brackets && c == b'[' {
+            if let Some(level) = self.long_bracket_level(self.pos) {
+                self.pos = self.long_bracket_end(self.pos, level);
+                return true;
+            }
+        }
+        if c == b'`' {
+            match syntax.backticks {
+                Backticks::None => {}
+                Backticks::Raw => {
+                    self.pos = self
+                        .find(self.pos + 1, "`")
+                        .map_or(self.bytes.len(), |i| i + 1);
+                    return true;
+                }
+                Backticks::Template => {
+                    self.template();
+                    return true;
+                }
+            }
+        }
+        if syntax.regex_literals && c == b'/' && self.regex_allowed() {
+            self.regex();
+            return true;
+        }
+        if syntax.heredocs != Heredocs::None && self.starts_with("<<") && self.heredoc_start() {
+            return true;
+        }
+        for &(quote, escape) in syntax.quotes {
+            if c != quote {
+                continue;
+            }
+            if syntax.word_apostrophe && quote == b'\'' && self.after_word_char() {
+                return false;
+            }
+            let triple = [quote; 3];
+            if syntax.triple_quotes && self.bytes[self.pos..].starts_with(&triple) {
+                self.pos += 3;
+                while self.pos < self.bytes.len() && !self.bytes[self.pos..].starts_with(&triple) {
+                    self.pos += if escape == Escape::Backslash && self.bytes[self.pos] == b'\\' {
+                        2
+                    } else {
+                        1
+                    };
+                }
+                self.pos = (self.pos + 3).min(self.bytes.len());
+            } else {
+                self.quoted(quote, escape, syntax.multiline_strings);
+            }
+            return true;
+        }
+        false
+    }
+
+    /// Skips a string starting at the opening `quote`.
+    fn quoted(&mut self, quote: u8, escape: Escape, multiline: bool) {
+        let len = self.bytes.len();
+        self.pos += 1;
+        while self.pos < len {
+            let b = self.bytes[self.pos];
+            match (b, escape) {
+                (b'\\', Escape::Backslash) | (b'`', Escape::Backtick) => self.pos += 2,
+                (_, Escape::Doubled) if b == quote && self.peek(1) == Some(quote) => self.pos += 2,
+                _ if b == quote => {
+                    self.pos += 1;
+                    return;
+                }
+                (b'\n', _) if !multiline => return,
+                _ => self.pos += 1,
+            }
+        }
+        self.pos = len;
+    }
+
+    /// Raw strings (`r#"..."#`), byte chars (`b'x'`), char literals and lifetimes.
+    fn rust_literal(&mut self) -> bool {
+        let c = self.bytes[self.pos];
+        if matches!(c, b'r' | b'b' | b'c') && !self.after_word_char() {
+            let mut i = self.pos + 1;
+            if c != b'r' && self.bytes.get(i) == Some(&b'r') {
+                i += 1;
+            } else if c == b'b' && self.bytes.get(i) == Some(&b'\'') {
+                self.pos = i;
+                self.char_or_lifetime();
+                return true;
+            }
+            let raw = c == b'r' || self.bytes.get(i - 1) == Some(&b'r');
+            if raw {
+                let hashes_start = i;
+                while self.bytes.get(i) == Some(&b'#') {
+                    i += 1;
+                }
+                // `r#type` is a raw identifier, not a string
+                if self.bytes.get(i) == Some(&b'"') {
+                    let closing = format!("\"{}", "#".repeat(i - hashes_start));
+                    self.pos = self
+                        .find(i + 1, &closing)
+                        .map_or(self.bytes.len(), |end| end + closing.len());
+                    return true;
+                }
+            }
+            return false;
+        }
+        if c == b'\'' {
+            self.char_or_lifetime();
+            return true;
+        }
+        false
+    }
+
+    fn char_or_lifetime(&mut self) {
+        if self.peek(1) == Some(b'\\') {
+            self.quoted(b'\'', Escape::Backslash, false);
+            return;
+        }
+        let next = self.src[self.pos + 1..].chars().next();
+        match next {
+            Some(ch) if self.bytes.get(self.pos + 1 + ch.len_utf8()) == Some(&b'\'') => {
+                self.pos += ch.len_utf8() + 2;
+            }
+            _ => self.pos += 1,
+        }
+    }
+
+    /// `R"delim( ... )delim"`, with an optional `u8`, `u`, `U` or `L` prefix before the `R`.
+    fn cpp_raw_string(&mut self) -> bool {
+        let word_start = self.src[..self.pos]
+            .rfind(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .map_or(0, |i| i + 1);
+        if !matches!(
+            &self.src[word_start..self.pos],
+            "R" | "u8R" | "uR" | "UR" | "LR"
+        ) {
+            return false;
+        }
+        let Some(open) = self.find(self.pos + 1, "(") else {
+            return false;
+        };
+        let delim = &self.src[self.pos + 1..open];
+        if delim.len() > 16 || delim.contains(|c: char| c.is_whitespace() || c == '\\') {
+            return false;
+        }
+        let closing = format!("){}\"", delim);
+        self.pos = self
+            .find(open + 1, &closing)
+            .map_or(self.bytes.len(), |end| end + closing.len());
+        true
+    }
+
+    /// `` `text ${ code } text` ``; comments inside `${ }` are still comments.
+    fn template(&mut self) {
+        let len = self.bytes.len();
+        self.pos += 1;
+        while self.pos < len {
+            match self.bytes[self.pos] {
+                b'\\' => self.pos += 2,
+                b'`' => {
+                    self.pos += 1;
+                    return;
+                }
+                b'$' if self.peek(1) == Some(b'{') => {
+                    self.pos += 2;
+                    self.run(true);
+                }
+                _ => self.pos += 1,
+            }
+        }
+        self.pos = len;
+    }
+
+    /// A `/` starts a regex after an operator or keyword, and means division after a value.
+    fn regex_allowed(&self) -> bool {
+        const KEYWORDS: &[&str] = &[
+            "return",
+            "typeof",
+            "instanceof",
+            "in",
+            "of",
+            "new",
+            "delete",
+            "void",
+            "throw",
+            "case",
+            "do",
+            "else",
+            "yield",
+            "await",
+        ];
+        let before = self.src[..self.pos].trim_end();
+        match before.bytes().last() {
+            None => true,
+            Some(b) if is_word_byte(b) => {
+                let word_start = before
+                    .rfind(|c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '$')
+                    .map_or(0, |i| i + 1);
+                KEYWORDS.contains(&&before[word_start..])
+            }
+            Some(b) => !matches!(b, b')' | b']' | b'}' | b'"' | b'\'' | b'`'),
+        }
+    }
+
+    fn regex(&mut self) {
+        let len = self.bytes.len();
+        let start = self.pos;
+        self.pos += 1;
+        let mut in_class = false;
+        while self.pos < len {
+            match self.bytes[self.pos] {
+                b'\\' => self.pos += 2,
+                b'[' => {
+                    in_class = true;
+                    self.pos += 1;
+                }
+                b']' => {
+                    in_class = false;
+                    self.pos += 1;
+                }
+                b'/' if !in_class => {
+                    self.pos += 1;
+                    return;
+                }
+                // Not a regex after all; treat the slash as an operator
+                b'\n' => {
+                    self.pos = start + 1;
+                    return;
+                }
+                _ => self.pos += 1,
+            }
+        }
+        self.pos = start + 1;
+    }
+
+    /// Registers a heredoc whose body starts on the next line.
+    fn heredoc_start(&mut self) -> bool {
+        let mut i = self.pos + 2;
+        // `<<<` is a PHP heredoc but a here-string in shell
+        if self.syntax.heredocs == Heredocs::Script && self.bytes.get(i) == Some(&b'<') {
+            i += 1;
+        }
+        if matches!(self.bytes.get(i), Some(b'-' | b'~')) {
+            i += 1;
+        }
+        if self.syntax.heredocs == Heredocs::Shell {
+            while matches!(self.bytes.get(i), Some(b' ' | b'\t')) {
+                i += 1;
+            }
+        }
+        let quote = match self.bytes.get(i) {
+            Some(&q @ (b'\'' | b'"')) => {
+                i += 1;
+                Some(q)
+            }
+            _ => None,
+        };
+        let word_start = i;
+        while self.bytes.get(i).copied().is_some_and(is_word_byte) {
+            i += 1;
+        }
+        if i == word_start || self.bytes[word_start].is_ascii_digit() {
+            return false;
+        }
+        let word = self.src[word_start..i].to_string();
+        if let Some(q) = quote {
+            if self.bytes.get(i) != Some(&q) {
+                return false;
+            }
+            i += 1;
+        }
+        self.heredocs.push(word);
+        self.pos = i;
+        true
+    }
+
+    /// Skips the bodies of pending heredocs, leaving the position at the end of the last terminator.
+    fn skip_heredoc_bodies(&mut self) {
+        for word in std::mem::take(&mut self.heredocs) {
+            loop {
+                if self.pos >= self.bytes.len() {
+                    return;
+                }
+                let end = self.end_of_line(self.pos);
+                let text = self.src[self.pos..end].trim_start();
+                let terminated = text
+                    .strip_prefix(word.as_str())
+                    .is_some_and(|rest| !rest.bytes().next().is_some_and(is_word_byte));
+                if terminated {
+                    self.pos = end;
+                    break;
+                }
+                self.pos = self.find(end, "\n").map_or(self.bytes.len(), |i| i + 1);
+            }
+        }
+    }
+
+    /// The number of `=` in a Lua long bracket opening at `at`, like `[==[`.
+    fn long_bracket_level(&self, at: usize) -> Option<usize> {
+        if self.bytes.get(at) != Some(&b'[') {
+            return None;
+        }
+        let mut i = at + 1;
+        while self.bytes.get(i) == Some(&b'=') {
+            i += 1;
+        }
+        (self.bytes.get(i) == Some(&b'[')).then_some(i - at - 1)
+    }
+
+    fn long_bracket_end(&self, at: usize, level: usize) -> usize {
+        let closing = format!("]{}]", "=".repeat(level));
+        self.find(at + level + 2, &closing)
+            .map_or(self.bytes.len(), |end| end + closing.len())
+    }
+}
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
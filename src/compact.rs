@@ -0,0 +1,233 @@
+use crate::comments::{self, Syntax};
+use crate::lines;
+use serde::{Deserialize, Serialize};
+
+/// How `--compact` rewrites leading indentation.
+#[derive(Serialize, Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum IndentMode {
+    /// Leave indentation as it is.
+    #[default]
+    Keep,
+    /// One tab per indentation level.
+    Tabs,
+    /// One space per indentation level.
+    Minimal,
+}
+
+/// Languages whose indentation carries no meaning, so it can be rewritten.
+/// Python, YAML, Haskell, Markdown and Makefiles are deliberately absent, as are
+/// markup languages, whose `<pre>` and `xml:space="preserve"` contents keep their spaces.
+const FREE_FORM: &[&str] = &[
+    "rust",
+    "c",
+    "cpp",
+    "csharp",
+    "go",
+    "java",
+    "kotlin",
+    "swift",
+    "scala",
+    "javascript",
+    "jsx",
+    "typescript",
+    "tsx",
+    "css",
+    "scss",
+    "less",
+    "php",
+    "dart",
+    "groovy",
+    "protobuf",
+    "json",
+    "jsonc",
+    "sql",
+    "lua",
+];
+
+/// Languages left untouched: trailing whitespace or blank lines are content there.
+const VERBATIM: &[&str] = &["diff", "markdown"];
+
+/// Indentation steps considered when detecting a file's indent unit.
+const INDENT_UNITS: &[usize] = &[2, 3, 4, 8];
+
+/// Normalizes CRLF to LF, trims trailing whitespace, collapses runs of blank lines and
+/// drops leading and trailing blank lines. Lines inside multi-line string literals, heredocs
+/// and YAML block scalars are kept as they are. Indentation is rewritten per `indent` only
+/// for free-form languages. `language` comes from `language::detect`, empty if unknown.
+/// Also returns the indices of the lines that were kept.
+pub fn compact(content: &str, language: &str, indent: IndentMode) -> (String, Vec<usize>) {
+    if VERBATIM.contains(&language) {
+        return (content.to_string(), (0..lines::count(content)).collect());
+    }
+    let content = content.replace("\r\n", "\n");
+    let lines: Vec<&str> = content.split('\n').collect();
+    let protected = match comments::syntax_for(language) {
+        Some(syntax) => literal_lines(&content, &lines, syntax),
+        None => vec![Protected::default(); lines.len()],
+    };
+    let protected = if language == "yaml" {
+        block_scalar_lines(&lines, protected)
+    } else {
+        protected
+    };
+
+    let unit = match indent {
+        IndentMode::Keep => None,
+        _ if !FREE_FORM.contains(&language) => None,
+        _ => indent_unit(&lines, &protected),
+    };
+
+    let mut out = String::with_capacity(content.len());
+    let mut kept = Vec::with_capacity(lines.len());
+    let mut blank_run = 0;
+    for (idx, (line, protect)) in lines.iter().zip(&protected).enumerate() {
+        if protect.start {
+            // Blank lines held back before a literal line are part of the code around it
+            push_blanks(&mut out, &mut kept, &mut blank_run, idx);
+            kept.push(idx);
+            let text = if protect.end { line } else { line.trim_end() };
+            out.push_str(text);
+            out.push('\n');
+            continue;
+        }
+        let text = if protect.end { *line } else { line.trim_end() };
+        if text.is_empty() {
+            blank_run += 1;
+            continue;
+        }
+        push_blanks(&mut out, &mut kept, &mut blank_run, idx);
+        kept.push(idx);
+        match (unit, indent) {
+            (Some(unit), IndentMode::Tabs | IndentMode::Minimal) => {
+                let body = text.trim_start_matches(' ');
+                let spaces = text.len() - body.len();
+                let level = if indent == IndentMode::Tabs {
+                    "\t"
+                } else {
+                    " "
+                };
+                out.push_str(&level.repeat(spaces / unit));
+                out.push_str(&" ".repeat(spaces % unit));
+                out.push_str(body);
+            }
+            _ => out.push_str(text),
+        }
+        out.push('\n');
+    }
+
+    // Keep the file's final newline, or its absence
+    let end = out.trim_end_matches('\n').len();
+    out.truncate(end);
+    if content.ends_with('\n') && !out.is_empty() {
+        out.push('\n');
+    }
+    kept.truncate(lines::count(&out));
+    (out, kept)
+}
+
+/// Emits one blank line for a run of them ending before line `next`, except at the
+/// start of the output.
+fn push_blanks(out: &mut String, kept: &mut Vec<usize>, blank_run: &mut usize, next: usize) {
+    if *blank_run > 0 && !out.is_empty() {
+        out.push('\n');
+        kept.push(next - 1);
+    }
+    *blank_run = 0;
+}
+
+/// Parts of a line that belong to a literal.
+#[derive(Debug, Default, Clone, Copy)]
+struct Protected {
+    /// The line begins inside a literal: keep its indentation, and keep it even when blank.
+    start: bool,
+    /// The line break ends inside a literal: keep its trailing whitespace.
+    end: bool,
+}
+
+fn literal_lines(content: &str, lines: &[&str], syntax: &Syntax) -> Vec<Protected> {
+    // (start, end) byte offsets of each line, without its line break
+    let mut spans = Vec::with_capacity(lines.len());
+    let mut offset = 0;
+    for line in lines {
+        spans.push((offset, offset + line.len()));
+        offset += line.len() + 1;
+    }
+    let mut protected = vec![Protected::default(); lines.len()];
+    for literal in comments::find_multiline_literals(content, syntax) {
+        let first = spans.partition_point(|&(_, end)| end < literal.start);
+        for (idx, &(start, end)) in spans.iter().enumerate().skip(first) {
+            if start >= literal.end {
+                break;
+            }
+            protected[idx].start |= start >= literal.start;
+            protected[idx].end |= literal.start < end && end < literal.end;
+        }
+    }
+    protected
+}
+
+/// Marks the contents of YAML `|` and `>` block scalars, where blank lines and
+/// trailing whitespace are part of the value.
+fn block_scalar_lines(lines: &[&str], mut protected: Vec<Protected>) -> Vec<Protected> {
+    let mut parent: Option<usize> = None;
+    for (idx, line) in lines.iter().enumerate() {
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        if let Some(parent_indent) = parent {
+            if line.trim().is_empty() || indent > parent_indent {
+                protected[idx] = Protected {
+                    start: true,
+                    end: true,
+                };
+                continue;
+            }
+            parent = None;
+        }
+        if opens_block_scalar(line) {
+            parent = Some(indent);
+        }
+    }
+    protected
+}
+
+/// `key: |`, `- >-` or `key: |2 # note`.
+fn opens_block_scalar(line: &str) -> bool {
+    let code = match line.find(" #") {
+        Some(i) => &line[..i],
+        None => line,
+    };
+    let code = code.trim_end();
+    let start = code.rfind([' ', '\t']).map_or(0, |i| i + 1);
+    let (head, indicator) = code.split_at(start);
+    let head = head.trim_end();
+    let valid_head = head.is_empty() || head.ends_with(':') || head.ends_with('-');
+    let mut chars = indicator.chars();
+    valid_head
+        && matches!(chars.next(), Some('|' | '>'))
+        && chars.all(|c| matches!(c, '+' | '-' | '1'..='9'))
+        && indicator.len() <= 3
+}
+
+/// The most common indentation step among space-indented lines, if it is a usual one.
+fn indent_unit(lines: &[&str], protected: &[Protected]) -> Option<usize> {
+    let mut counts = [0usize; 9];
+    let mut previous = 0;
+    for (line, protect) in lines.iter().zip(protected) {
+        if protect.start || line.trim().is_empty() {
+            continue;
+        }
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        if line[indent..].starts_with('\t') {
+            return None;
+        }
+        if indent > previous && indent - previous < counts.len() {
+            counts[indent - previous] += 1;
+        }
+        previous = indent;
+    }
+    INDENT_UNITS
+        .iter()
+        .copied()
+        .filter(|&unit| counts[unit] > 0)
+        .max_by_key(|&unit| (counts[unit], unit))
+}
@@ -1,19 +1,71 @@
+use crate::comments::CommentMode;
+use crate::compact::IndentMode;
+use crate::license::LicenseMode;
+use crate::output::OutputFormat;
+use crate::relevance::QueryOrder;
+use crate::toc::TocStyle;
+use crate::tokens::Tokenizer;
 use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
+use serde::de::{self, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml::Spanned;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// Effective configuration after merging defaults, global, local and CLI layers.
+#[derive(Serialize, Debug, Clone)]
 pub struct AppConfig {
     pub exclude_ext: Vec<String>,
     pub exclude_dir: Vec<String>,
     pub exclude_file: Vec<String>,
+    /// Gitignore-style globs. When non-empty, only matching files are kept.
+    pub include: Vec<String>,
+    /// Gitignore-style globs for files and directories to drop.
+    pub exclude: Vec<String>,
     pub max_file_mb: u64,
     pub max_total_mb: u64,
     pub use_gitignore: bool,
+    /// Honor `.ctxsnapignore` files at every level of the tree.
+    pub use_ctxsnapignore: bool,
     pub include_lockfiles: bool,
-    pub remove_comments: bool,
+    /// Which comments to strip from file contents.
+    pub comment_mode: CommentMode,
+    /// What to do with license headers, independently of `comment_mode`.
+    pub license_headers: LicenseMode,
+    /// Trim trailing whitespace, collapse blank lines and normalize line endings.
+    pub compact: bool,
+    /// Indentation rewrite applied by `compact` to free-form languages.
+    pub compact_indent: IndentMode,
+    /// Prefix each line with its number in the original file.
+    pub line_numbers: bool,
+    /// Redact secrets found in file contents.
+    pub scan_secrets: bool,
+    /// Extra secret regexes, redacted as `custom`.
+    pub secret_patterns: Vec<String>,
+    /// Abort instead of writing a snapshot that had secrets redacted.
+    pub fail_on_secrets: bool,
     pub depth: usize,
+    pub tokenizer: Tokenizer,
+    pub max_file_tokens: Option<u64>,
+    pub max_total_tokens: Option<u64>,
+    pub format: OutputFormat,
+    /// Table of contents layout.
+    pub toc: TocStyle,
+    /// List omitted files in a tree TOC as well.
+    pub toc_omitted: bool,
+    /// Collapse TOC tree directories this many levels below the root.
+    pub toc_depth: Option<usize>,
+    /// Order of the files in a `--query` snapshot.
+    pub query_order: QueryOrder,
+    pub part_max_mb: Option<u64>,
+    pub part_max_tokens: Option<u64>,
+    /// Add HEAD, branch, dirty state and recent commits to the header.
+    pub git_info: bool,
+    /// Add each file's last-modifying commit under its heading.
+    pub git_file_info: bool,
 }
 
 impl Default for AppConfig {
@@ -45,72 +97,800 @@ impl Default for AppConfig {
                 .into_iter()
                 .map(String::from)
                 .collect(),
+            include: Vec::new(),
+            exclude: Vec::new(),
             max_file_mb: 10,
             max_total_mb: 200,
             use_gitignore: true,
+            use_ctxsnapignore: true,
             include_lockfiles: false,
-            remove_comments: false,
+            comment_mode: CommentMode::None,
+            license_headers: LicenseMode::Keep,
+            compact: false,
+            compact_indent: IndentMode::Keep,
+            line_numbers: false,
+            scan_secrets: true,
+            secret_patterns: Vec::new(),
+            fail_on_secrets: false,
             depth: 50,
+            tokenizer: Tokenizer::default(),
+            max_file_tokens: None,
+            max_total_tokens: None,
+            format: OutputFormat::default(),
+            toc: TocStyle::default(),
+            toc_omitted: false,
+            toc_depth: None,
+            query_order: QueryOrder::default(),
+            part_max_mb: None,
+            part_max_tokens: None,
+            git_info: false,
+            git_file_info: false,
         }
     }
 }
 
-impl AppConfig {
-    /// Load global config from next to the executable.
-    /// Creates it with defaults if it doesn't exist.
-    pub fn load_global() -> Result<Self> {
-        let exe_path = std::env::current_exe().context("Failed to get executable path")?;
-        let global_path = exe_path
-            .parent()
-            .context("Executable has no parent directory")?
-            .join("ctxsnap.toml");
-
-        if global_path.exists() {
-            // FIX: If reading or parsing fails (due to race conditions in tests),
-            // log a warning and use defaults. DO NOT CRASH.
-            match fs::read_to_string(&global_path) {
-                Ok(content) => match toml::from_str(&content) {
-                    Ok(config) => Ok(config),
-                    Err(e) => {
-                        // This happens when multiple tests read/write simultaneously
-                        eprintln!("Warning: Global config corrupted (race condition?): {}", e);
-                        Ok(Self::default())
+/// Environment variable naming the global config file.
+pub const CONFIG_ENV: &str = "CTXSNAP_CONFIG";
+
+/// Name of the per-project config file in the snapshot root.
+pub const LOCAL_CONFIG: &str = "ctxsnap.toml";
+
+/// `schema_version` written by this release. Files without the key are read as this version.
+/// Bump it when a key changes meaning, and upgrade older files in `read_file`.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Config keys in declaration order, for `--show-config` and key validation.
+const FIELDS: &[&str] = &[
+    "exclude_ext",
+    "exclude_dir",
+    "exclude_file",
+    "include",
+    "exclude",
+    "max_file_mb",
+    "max_total_mb",
+    "use_gitignore",
+    "use_ctxsnapignore",
+    "include_lockfiles",
+    "comment_mode",
+    "license_headers",
+    "compact",
+    "compact_indent",
+    "line_numbers",
+    "scan_secrets",
+    "secret_patterns",
+    "fail_on_secrets",
+    "depth",
+    "tokenizer",
+    "max_file_tokens",
+    "max_total_tokens",
+    "format",
+    "toc",
+    "toc_omitted",
+    "toc_depth",
+    "query_order",
+    "part_max_mb",
+    "part_max_tokens",
+    "git_info",
+    "git_file_info",
+];
+
+/// Older key names that are still accepted.
+const ALIASES: &[&str] = &["remove_comments"];
+
+/// A list setting in a config layer.
+/// A plain array extends the lower layers; `{ replace = [...] }` discards them.
+#[derive(Debug, Clone)]
+pub enum ListValue {
+    Extend(Vec<String>),
+    Explicit {
+        replace: Option<Vec<String>>,
+        extend: Option<Vec<String>>,
+    },
+}
+
+impl<'de> Deserialize<'de> for ListValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct ListVisitor;
+
+        impl<'de> Visitor<'de> for ListVisitor {
+            type Value = ListValue;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an array of strings or a `{ replace, extend }` table")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> std::result::Result<ListValue, A::Error> {
+                let mut items = Vec::new();
+                while let Some(item) = seq.next_element()? {
+                    items.push(item);
+                }
+                Ok(ListValue::Extend(items))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> std::result::Result<ListValue, A::Error> {
+                let (mut replace, mut extend) = (None, None);
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "replace" => replace = Some(map.next_value()?),
+                        "extend" => extend = Some(map.next_value()?),
+                        _ => return Err(de::Error::unknown_field(&key, &["replace", "extend"])),
                     }
-                },
-                Err(e) => {
-                    eprintln!("Warning: Could not read global config: {}", e);
-                    Ok(Self::default())
                 }
+                Ok(ListValue::Explicit { replace, extend })
+            }
+        }
+
+        deserializer.deserialize_any(ListVisitor)
+    }
+}
+
+/// One configuration source. Every field is optional; unset fields keep the lower layer's value.
+#[derive(Debug, Default, Clone)]
+pub struct ConfigLayer {
+    pub exclude_ext: Option<ListValue>,
+    pub exclude_dir: Option<ListValue>,
+    pub exclude_file: Option<ListValue>,
+    pub include: Option<ListValue>,
+    pub exclude: Option<ListValue>,
+    pub max_file_mb: Option<u64>,
+    pub max_total_mb: Option<u64>,
+    pub use_gitignore: Option<bool>,
+    pub use_ctxsnapignore: Option<bool>,
+    pub include_lockfiles: Option<bool>,
+    pub comment_mode: Option<CommentMode>,
+    pub license_headers: Option<LicenseMode>,
+    pub compact: Option<bool>,
+    pub compact_indent: Option<IndentMode>,
+    pub line_numbers: Option<bool>,
+    pub scan_secrets: Option<bool>,
+    pub secret_patterns: Option<ListValue>,
+    pub fail_on_secrets: Option<bool>,
+    pub depth: Option<usize>,
+    pub tokenizer: Option<Tokenizer>,
+    pub max_file_tokens: Option<u64>,
+    pub max_total_tokens: Option<u64>,
+    pub format: Option<OutputFormat>,
+    pub toc: Option<TocStyle>,
+    pub toc_omitted: Option<bool>,
+    pub toc_depth: Option<usize>,
+    pub query_order: Option<QueryOrder>,
+    pub part_max_mb: Option<u64>,
+    pub part_max_tokens: Option<u64>,
+    pub git_info: Option<bool>,
+    pub git_file_info: Option<bool>,
+}
+
+impl ConfigLayer {
+    /// Reads the value for `key` if it is a config field; returns false for any other key.
+    fn read_field<'de, A: MapAccess<'de>>(
+        &mut self,
+        key: &str,
+        map: &mut A,
+    ) -> std::result::Result<bool, A::Error> {
+        // `remove_comments = true` predates `comment_mode`
+        if key == "remove_comments" {
+            let remove: bool = map.next_value()?;
+            self.comment_mode = Some(if remove {
+                CommentMode::All
+            } else {
+                CommentMode::None
+            });
+            return Ok(true);
+        }
+        macro_rules! fields {
+            ($($field:ident),*) => {
+                match key {
+                    $(stringify!($field) => self.$field = Some(map.next_value()?),)*
+                    _ => return Ok(false),
+                }
+            };
+        }
+        fields!(
+            exclude_ext,
+            exclude_dir,
+            exclude_file,
+            include,
+            exclude,
+            max_file_mb,
+            max_total_mb,
+            use_gitignore,
+            use_ctxsnapignore,
+            include_lockfiles,
+            comment_mode,
+            license_headers,
+            compact,
+            compact_indent,
+            line_numbers,
+            scan_secrets,
+            secret_patterns,
+            fail_on_secrets,
+            depth,
+            tokenizer,
+            max_file_tokens,
+            max_total_tokens,
+            format,
+            toc,
+            toc_omitted,
+            toc_depth,
+            query_order,
+            part_max_mb,
+            part_max_tokens,
+            git_info,
+            git_file_info
+        );
+        Ok(true)
+    }
+
+    /// The first value outside its allowed range, as `(key, message)`.
+    /// Applies to config files and command-line flags alike.
+    pub fn range_error(&self) -> Option<(&'static str, &'static str)> {
+        let zero = |v: Option<u64>| v == Some(0);
+        let above = |v: Option<u64>, max: u64| v.is_some_and(|v| v > max);
+        [
+            (
+                zero(self.max_file_mb),
+                "max_file_mb",
+                "max_file_mb must be positive",
+            ),
+            (
+                above(self.max_file_mb, 1024),
+                "max_file_mb",
+                "max_file_mb cannot exceed 1GB",
+            ),
+            (
+                zero(self.max_total_mb),
+                "max_total_mb",
+                "max_total_mb must be positive",
+            ),
+            (
+                above(self.max_total_mb, 10240),
+                "max_total_mb",
+                "max_total_mb cannot exceed 10GB",
+            ),
+            (
+                zero(self.max_file_tokens),
+                "max_file_tokens",
+                "max_file_tokens must be positive",
+            ),
+            (
+                zero(self.max_total_tokens),
+                "max_total_tokens",
+                "max_total_tokens must be positive",
+            ),
+            (
+                zero(self.part_max_mb),
+                "part_max_mb",
+                "part_max_mb must be positive",
+            ),
+            (
+                above(self.part_max_mb, 10240),
+                "part_max_mb",
+                "part_max_mb cannot exceed 10GB",
+            ),
+            (
+                zero(self.part_max_tokens),
+                "part_max_tokens",
+                "part_max_tokens must be positive",
+            ),
+            (
+                self.depth.is_some_and(|d| d == 0 || d >= 1000),
+                "depth",
+                "depth must be between 1 and 999",
+            ),
+            (
+                self.toc_depth == Some(0),
+                "toc_depth",
+                "toc_depth must be positive",
+            ),
+        ]
+        .into_iter()
+        .find(|(failed, ..)| *failed)
+        .map(|(_, field, message)| (field, message))
+    }
+}
+
+/// A `[profile.<name>]` table: a config layer selected with `--profile`.
+#[derive(Debug, Default, Clone)]
+pub struct Profile {
+    /// Profile applied before this one.
+    pub inherits: Option<String>,
+    pub settings: ConfigLayer,
+}
+
+/// Contents of a config file: base settings plus named profiles.
+#[derive(Debug, Default)]
+struct ConfigFile {
+    settings: ConfigLayer,
+    profile: BTreeMap<String, Profile>,
+}
+
+/// A config file or profile table as written, before validation.
+/// Keys keep their spans so errors can point at the offending line.
+#[derive(Default)]
+struct RawTable {
+    settings: ConfigLayer,
+    schema_version: Option<u32>,
+    inherits: Option<String>,
+    profile: BTreeMap<String, RawTable>,
+    keys: Vec<(String, Range<usize>)>,
+}
+
+impl<'de> Deserialize<'de> for RawTable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct RawVisitor;
+
+        impl<'de> Visitor<'de> for RawVisitor {
+            type Value = RawTable;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a table")
             }
-        } else {
-            let config = Self::default();
-            // Best effort write: If it fails (read-only or race condition), it doesn't matter.
-            if let Ok(content) = toml::to_string_pretty(&config) {
-                let _ = fs::write(&global_path, content);
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> std::result::Result<RawTable, A::Error> {
+                let mut raw = RawTable::default();
+                while let Some(key) = map.next_key::<Spanned<String>>()? {
+                    let span = key.span();
+                    let key = key.into_inner();
+                    // Keys that are misplaced or unknown are reported by `check_table`
+                    match key.as_str() {
+                        "schema_version" => raw.schema_version = Some(map.next_value()?),
+                        "inherits" => raw.inherits = Some(map.next_value()?),
+                        "profile" => raw.profile = map.next_value()?,
+                        _ => {
+                            if !raw.settings.read_field(&key, &mut map)? {
+                                map.next_value::<IgnoredAny>()?;
+                            }
+                        }
+                    }
+                    raw.keys.push((key, span));
+                }
+                Ok(raw)
             }
-            Ok(config)
         }
+
+        deserializer.deserialize_map(RawVisitor)
     }
+}
+
+/// Written by `--init` after the base settings.
+const EXAMPLE_PROFILES: &str = r#"
+# Profiles overlay the settings above and are selected with `--profile <name>`.
+# `inherits` applies another profile first.
+[profile.review]
+max_total_tokens = 200000
+
+[profile.api]
+inherits = "review"
+include = ["src/**"]
+exclude = ["**/tests/**", "**/*_test.*"]
+comment_mode = "non-doc"
 
-    /// Load local config from project root. Returns None if it doesn't exist.
-    pub fn load_local(root: &Path) -> Result<Option<Self>> {
-        let local_path = root.join("ctxsnap.toml");
+[profile.tests]
+inherits = "review"
+include = ["tests/**", "**/*_test.*", "**/test_*.*"]
+format = "xml"
+"#;
+
+/// Where a setting came from, lowest precedence first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Layer {
+    Default,
+    Global,
+    Local,
+    Profile(String),
+    Cli,
+}
+
+impl Layer {
+    pub fn name(&self) -> String {
+        match self {
+            Layer::Default => "default".to_string(),
+            Layer::Global => "global".to_string(),
+            Layer::Local => "local".to_string(),
+            Layer::Profile(name) => format!("profile {}", name),
+            Layer::Cli => "cli".to_string(),
+        }
+    }
+}
+
+/// Layers that contributed to each field. Lists can combine several layers.
+#[derive(Debug, Default)]
+pub struct ConfigSources {
+    fields: BTreeMap<&'static str, Vec<Layer>>,
+    // Config files that were read, for `--show-config`
+    files: Vec<(Layer, PathBuf)>,
+    // Selected profile and its ancestors, base first
+    profiles: Vec<String>,
+}
+
+impl ConfigSources {
+    fn set(&mut self, field: &'static str, layer: &Layer) {
+        self.fields.insert(field, vec![layer.clone()]);
+    }
+
+    fn add(&mut self, field: &'static str, layer: &Layer) {
+        let layers = self
+            .fields
+            .entry(field)
+            .or_insert_with(|| vec![Layer::Default]);
+        if !layers.contains(layer) {
+            layers.push(layer.clone());
+        }
+    }
+
+    /// e.g. `default + local`
+    pub fn describe(&self, field: &str) -> String {
+        match self.fields.get(field) {
+            Some(layers) => layers
+                .iter()
+                .map(Layer::name)
+                .collect::<Vec<_>>()
+                .join(" + "),
+            None => Layer::Default.name(),
+        }
+    }
+}
+
+fn merge_value<T>(
+    target: &mut T,
+    value: Option<T>,
+    field: &'static str,
+    layer: &Layer,
+    sources: &mut ConfigSources,
+) {
+    if let Some(v) = value {
+        *target = v;
+        sources.set(field, layer);
+    }
+}
+
+fn merge_list(
+    target: &mut Vec<String>,
+    value: Option<ListValue>,
+    field: &'static str,
+    layer: &Layer,
+    sources: &mut ConfigSources,
+) {
+    let (replace, extend) = match value {
+        None => return,
+        Some(ListValue::Extend(items)) => (None, items),
+        Some(ListValue::Explicit { replace, extend }) => (replace, extend.unwrap_or_default()),
+    };
+    if let Some(items) = replace {
+        *target = items;
+        sources.set(field, layer);
+    }
+    // Duplicates are skipped so re-listing a default is harmless
+    for item in extend {
+        if !target.contains(&item) {
+            if target.is_empty() {
+                sources.set(field, layer);
+            } else {
+                sources.add(field, layer);
+            }
+            target.push(item);
+        }
+    }
+}
+
+impl AppConfig {
+    /// Merges defaults -> global -> local (from `root`) -> `profile` -> `cli`.
+    /// `global` is the `--config` path, if given.
+    pub fn load(
+        root: &Path,
+        global: Option<&Path>,
+        profile: Option<&str>,
+        cli: ConfigLayer,
+    ) -> Result<(Self, ConfigSources)> {
+        let mut config = Self::default();
+        let mut sources = ConfigSources::default();
+        // Profiles from each file, global first
+        let mut profiles = Vec::new();
+
+        if let Some((path, explicit)) = global_config_path(global) {
+            // An explicitly named file must exist; the default location is optional
+            if explicit || path.exists() {
+                let file = read_file(&path)?;
+                config.merge(file.settings, &Layer::Global, &mut sources);
+                profiles.push(file.profile);
+                sources.files.push((Layer::Global, path));
+            }
+        }
+        let local_path = root.join(LOCAL_CONFIG);
         if local_path.exists() {
-            let content = fs::read_to_string(&local_path)
-                .context(format!("Failed to read local config: {:?}", local_path))?;
-            let config = toml::from_str(&content).context("Local config is corrupted")?;
-            Ok(Some(config))
-        } else {
-            Ok(None)
+            let file = read_file(&local_path)?;
+            config.merge(file.settings, &Layer::Local, &mut sources);
+            profiles.push(file.profile);
+            sources.files.push((Layer::Local, local_path));
+        }
+        if let Some(name) = profile {
+            let chain = resolve_profile(name, &profiles)?;
+            for name in &chain {
+                // A profile defined in both files applies the global table, then the local one
+                for defined in &profiles {
+                    if let Some(p) = defined.get(name) {
+                        let layer = Layer::Profile(name.clone());
+                        config.merge(p.settings.clone(), &layer, &mut sources);
+                    }
+                }
+            }
+            sources.profiles = chain;
         }
+        config.merge(cli, &Layer::Cli, &mut sources);
+
+        Ok((config, sources))
     }
 
-    /// Save config to local project root.
-    pub fn save_local(&self, root: &Path) -> Result<()> {
-        let local_path = root.join("ctxsnap.toml");
-        let content = toml::to_string_pretty(self)?;
-        fs::write(&local_path, content)
-            .context(format!("Failed to write local config: {:?}", local_path))?;
+    /// Applies the fields set in `layer` on top of the current values.
+    pub fn merge(&mut self, layer: ConfigLayer, from: &Layer, sources: &mut ConfigSources) {
+        macro_rules! merge {
+            (lists: $($list:ident),*; values: $($value:ident),*; options: $($option:ident),*) => {
+                $(merge_list(&mut self.$list, layer.$list, stringify!($list), from, sources);)*
+                $(merge_value(&mut self.$value, layer.$value, stringify!($value), from, sources);)*
+                $(merge_value(
+                    &mut self.$option,
+                    layer.$option.map(Some),
+                    stringify!($option),
+                    from,
+                    sources,
+                );)*
+            };
+        }
+        merge!(
+            lists: exclude_ext, exclude_dir, exclude_file, include, exclude, secret_patterns;
+            values: max_file_mb, max_total_mb, use_gitignore, use_ctxsnapignore,
+                include_lockfiles, comment_mode, license_headers, compact, compact_indent,
+                line_numbers, scan_secrets, fail_on_secrets, depth, tokenizer, format, toc, toc_omitted, query_order,
+                git_info, git_file_info;
+            options: max_file_tokens, max_total_tokens, part_max_mb, part_max_tokens, toc_depth
+        );
+    }
+
+    /// Renders the effective config as TOML, annotating each value with its layers.
+    pub fn describe(&self, sources: &ConfigSources) -> Result<String> {
+        let table = toml::Table::try_from(self)?;
+        let mut out = String::from(
+            "# Effective configuration (defaults -> global -> local -> profile -> cli)\n",
+        );
+        for (layer, path) in &sources.files {
+            out.push_str(&format!(
+                "# {} config: {}\n",
+                layer.name(),
+                crate::clean_path(path)
+            ));
+        }
+        if !sources.profiles.is_empty() {
+            out.push_str(&format!("# profile: {}\n", sources.profiles.join(" -> ")));
+        }
+        for field in FIELDS {
+            let line = match table.get(*field) {
+                Some(value) => format!("{} = {}", field, value),
+                None => format!("# {} is unset", field),
+            };
+            out.push_str(&format!("{}  # {}\n", line, sources.describe(field)));
+        }
+        Ok(out)
+    }
+
+    /// Writes the config as TOML, creating parent directories.
+    /// Refuses to replace an existing file unless `force` is set.
+    /// `example_profiles` appends sample `[profile.*]` tables.
+    pub fn save(&self, path: &Path, force: bool, example_profiles: bool) -> Result<()> {
+        anyhow::ensure!(
+            force || !path.exists(),
+            "Config already exists: {} (use --force to overwrite)",
+            crate::clean_path(path)
+        );
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .context(format!("Failed to create config directory: {:?}", parent))?;
+        }
+        let mut content = format!("schema_version = {}\n", SCHEMA_VERSION);
+        content.push_str(&toml::to_string_pretty(self)?);
+        if example_profiles {
+            content.push_str(EXAMPLE_PROFILES);
+        }
+        fs::write(path, content).context(format!("Failed to write config: {:?}", path))?;
         Ok(())
     }
 }
+
+/// Location of the global config: `--config`, then `$CTXSNAP_CONFIG`, then
+/// `$XDG_CONFIG_HOME/ctxsnap/config.toml` (`~/.config` or `%APPDATA%` when unset).
+/// The flag reports whether the path was named explicitly.
+pub fn global_config_path(flag: Option<&Path>) -> Option<(PathBuf, bool)> {
+    if let Some(path) = flag {
+        return Some((path.to_path_buf(), true));
+    }
+    if let Some(path) = std::env::var_os(CONFIG_ENV).filter(|p| !p.is_empty()) {
+        return Some((PathBuf::from(path), true));
+    }
+
+    // Relative XDG values are invalid per the spec and ignored
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| {
+            if cfg!(windows) {
+                std::env::var_os("APPDATA").map(PathBuf::from)
+            } else {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+            }
+        })?;
+    Some((config_home.join("ctxsnap").join("config.toml"), false))
+}
+
+/// Returns `name` and the profiles it inherits from, base first.
+/// Where both files define a profile, the local `inherits` wins.
+fn resolve_profile(name: &str, profiles: &[BTreeMap<String, Profile>]) -> Result<Vec<String>> {
+    let mut chain: Vec<String> = Vec::new();
+    let mut next = Some(name.to_string());
+    while let Some(current) = next {
+        if chain.contains(&current) {
+            chain.push(current);
+            anyhow::bail!("Profile inheritance cycle: {}", chain.join(" -> "));
+        }
+        let defined: Vec<&Profile> = profiles.iter().filter_map(|p| p.get(&current)).collect();
+        if defined.is_empty() {
+            let mut known: Vec<&str> = profiles
+                .iter()
+                .flat_map(|p| p.keys().map(String::as_str))
+                .collect();
+            known.sort_unstable();
+            known.dedup();
+            let known = if known.is_empty() {
+                "none defined".to_string()
+            } else {
+                format!("available: {}", known.join(", "))
+            };
+            match chain.last() {
+                Some(child) => anyhow::bail!(
+                    "Unknown profile {:?} inherited by {:?} ({})",
+                    current,
+                    child,
+                    known
+                ),
+                None => anyhow::bail!("Unknown profile {:?} ({})", current, known),
+            }
+        }
+        next = defined.iter().rev().find_map(|p| p.inherits.clone());
+        chain.push(current);
+    }
+    chain.reverse();
+    Ok(chain)
+}
+
+/// Reads and validates a config file. Every problem is reported as `path:line:column: message`.
+fn read_file(path: &Path) -> Result<ConfigFile> {
+    let content = fs::read_to_string(path).context(format!("Failed to read config: {:?}", path))?;
+    let raw: RawTable = toml::from_str(&content)
+        .map_err(|e| anyhow::anyhow!(located(path, &content, e.span(), e.message())))?;
+
+    let mut errors = Vec::new();
+    check_table(&raw, None, &mut errors);
+    if let Some(version) = raw.schema_version {
+        let span = key_span(&raw, "schema_version");
+        if version == 0 {
+            errors.push((span, "schema_version must be at least 1".to_string()));
+        } else if version > SCHEMA_VERSION {
+            errors.push((
+                span,
+                format!(
+                    "schema_version {} is newer than this ctxsnap supports ({}); upgrade ctxsnap",
+                    version, SCHEMA_VERSION
+                ),
+            ));
+        }
+    }
+    for (name, profile) in &raw.profile {
+        check_table(profile, Some(name), &mut errors);
+    }
+    if !errors.is_empty() {
+        errors.sort_by_key(|(span, _)| span.start);
+        let messages: Vec<String> = errors
+            .into_iter()
+            .map(|(span, message)| located(path, &content, Some(span), &message))
+            .collect();
+        anyhow::bail!("{}", messages.join("\n"));
+    }
+
+    Ok(ConfigFile {
+        settings: raw.settings,
+        profile: raw
+            .profile
+            .into_iter()
+            .map(|(name, p)| {
+                let profile = Profile {
+                    inherits: p.inherits,
+                    settings: p.settings,
+                };
+                (name, profile)
+            })
+            .collect(),
+    })
+}
+
+/// Collects unknown keys and out-of-range values in the top level or a `[profile.<name>]` table.
+fn check_table(raw: &RawTable, profile: Option<&str>, errors: &mut Vec<(Range<usize>, String)>) {
+    let extra: &[&str] = match profile {
+        Some(_) => &["inherits"],
+        None => &["schema_version", "profile"],
+    };
+    for (key, span) in &raw.keys {
+        if FIELDS.contains(&key.as_str())
+            || ALIASES.contains(&key.as_str())
+            || extra.contains(&key.as_str())
+        {
+            continue;
+        }
+        let mut message = match profile {
+            Some(name) => format!("unknown key `{}` in [profile.{}]", key, name),
+            None => format!("unknown key `{}`", key),
+        };
+        if let Some(known) = suggest(key, FIELDS.iter().chain(extra)) {
+            message.push_str(&format!(" (did you mean `{}`?)", known));
+        }
+        errors.push((span.clone(), message));
+    }
+    if let Some((field, message)) = raw.settings.range_error() {
+        errors.push((key_span(raw, field), message.to_string()));
+    }
+}
+
+fn key_span(raw: &RawTable, field: &str) -> Range<usize> {
+    raw.keys
+        .iter()
+        .find(|(key, _)| key == field)
+        .map(|(_, span)| span.clone())
+        .unwrap_or(0..0)
+}
+
+/// The closest known key within a small edit distance, for "did you mean" hints.
+fn suggest<'a>(key: &str, known: impl Iterator<Item = &'a &'a str>) -> Option<&'a str> {
+    known
+        .map(|k| (edit_distance(key, k), *k))
+        .filter(|(distance, k)| *distance <= (k.len() / 3).max(1))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, k)| k)
+}
+
+/// Levenshtein distance over characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// Formats `message` with the 1-based line and column of `span` in `content`.
+fn located(path: &Path, content: &str, span: Option<Range<usize>>, message: &str) -> String {
+    let path = crate::clean_path(path);
+    let Some(span) = span else {
+        return format!("{}: {}", path, message);
+    };
+    let before = &content[..span.start.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    format!("{}:{}:{}: {}", path, line, column, message)
+}
@@ -1,53 +1,189 @@
 use crate::config::AppConfig;
-use anyhow::Result;
-use ignore::WalkBuilder;
+use crate::git::{ChangeSet, Revision};
+use anyhow::{Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::{Match, WalkBuilder};
 use regex::Regex;
 use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
 
-// Regex for the strictly excluded output files: merged_YYYYMMDD_HHMMSS.md
+// Regex for the strictly excluded output files:
+// merged_YYYYMMDD_HHMMSS[_partN|_index].{md,json,xml}
 static MERGED_REGEX: OnceLock<Regex> = OnceLock::new();
 
+/// Per-directory ignore file for paths that belong in git but not in the snapshot.
+const IGNORE_FILENAME: &str = ".ctxsnapignore";
+
+// Exclude system/hidden directories
+const ABSOLUTE_EXCLUDE_DIRS: &[&str] = &[
+    ".git", ".ssh", ".aws", ".gnupg", ".kube", ".cargo", ".rustup",
+];
+
 /// Result of file discovery including any errors encountered
 pub struct DiscoveryResult {
     pub files: Vec<PathBuf>,
     pub errors: Vec<String>,
 }
 
+/// Name, extension and glob rules shared by the filesystem walk and `--rev` listings.
+#[derive(Clone)]
+struct Filters {
+    root: PathBuf,
+    exclude_dirs: HashSet<String>,
+    exclude_files: HashSet<String>,
+    exclude_exts: HashSet<String>,
+    include: Gitignore,
+    exclude: Gitignore,
+    include_prefixes: Vec<Vec<String>>,
+    // Includes that spell out a file name or extension, which re-admit default-excluded files
+    naming_includes: Vec<NamingInclude>,
+    has_include: bool,
+    include_lockfiles: bool,
+}
+
+impl Filters {
+    fn new(root: &Path, config: &AppConfig) -> Result<Self> {
+        // Lowercase normalization for case-insensitive matching
+        let lower = |list: &[String]| list.iter().map(|s| s.to_lowercase()).collect();
+        Ok(Self {
+            root: root.to_path_buf(),
+            exclude_dirs: lower(&config.exclude_dir),
+            exclude_files: lower(&config.exclude_file),
+            exclude_exts: lower(&config.exclude_ext),
+            include: build_globs(root, &config.include).context("Invalid include pattern")?,
+            exclude: build_globs(root, &config.exclude).context("Invalid exclude pattern")?,
+            include_prefixes: literal_prefixes(&config.include),
+            naming_includes: naming_includes(root, &config.include)?,
+            has_include: !config.include.is_empty(),
+            include_lockfiles: config.include_lockfiles,
+        })
+    }
+
+    /// False if the directory (never the root) and everything below it is pruned.
+    fn keep_dir(&self, path: &Path) -> bool {
+        let name_lower = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if ABSOLUTE_EXCLUDE_DIRS.contains(&name_lower.as_str()) {
+            return false;
+        }
+        if self.exclude.matched(path, true).is_ignore() {
+            return false;
+        }
+        // Default-excluded directories stay reachable for includes that name them
+        if self.exclude_dirs.contains(&name_lower) {
+            let rel = path.strip_prefix(&self.root).unwrap_or(path);
+            return reaches_prefix(rel, &self.include_prefixes);
+        }
+        true
+    }
+
+    fn keep_file(&self, path: &Path) -> bool {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy())
+            .unwrap_or_default();
+        let name_lower = name.to_lowercase(); // Normalize once
+
+        let regex = MERGED_REGEX.get_or_init(|| {
+            Regex::new(r"^merged_\d{8}_\d{6}(_part\d+|_index)?\.(md|json|xml)$").unwrap()
+        });
+
+        // 1. Snapshot outputs and internal config
+        if regex.is_match(&name) || name_lower == "ctxsnap.toml" || name_lower == IGNORE_FILENAME {
+            return false;
+        }
+
+        // Secret prefixes
+        if name_lower.starts_with(".env")
+            && !name_lower.ends_with(".example")
+            && !name_lower.ends_with(".sample")
+            && !name_lower.ends_with(".template")
+            && name_lower != ".envrc"
+        {
+            return false;
+        }
+
+        // 2. Include globs: whitelist, and explicit matches bypass the name lists
+        let explicitly_included = self.has_include
+            && self
+                .include
+                .matched_path_or_any_parents(path, false)
+                .is_ignore();
+        if self.has_include && !explicitly_included {
+            return false;
+        }
+        // Default-excluded directories are only entered along an include's literal
+        // path; a broad include like `*.rs` must not pick up siblings on the way.
+        let rel_dir = path
+            .parent()
+            .and_then(|p| p.strip_prefix(&self.root).ok())
+            .unwrap_or(Path::new(""));
+        if explicitly_included
+            && rel_dir.components().any(|c| {
+                self.exclude_dirs
+                    .contains(&c.as_os_str().to_string_lossy().to_lowercase())
+            })
+            && !within_prefix(rel_dir, &self.include_prefixes)
+        {
+            return false;
+        }
+
+        // Default-excluded names are only re-admitted by an include that spells out the
+        // name or extension and matches the file itself; a broad include like
+        // `crates/core/**` keeps them out, even next to an include naming `*.key`.
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        let named = explicitly_included
+            && self
+                .naming_includes
+                .iter()
+                .any(|include| include.names(path, &name_lower, extension.as_deref()));
+        if !named {
+            // 3. Lockfiles
+            if !self.include_lockfiles && is_lockfile(&name) {
+                return false;
+            }
+
+            // 4. Exclude files (check lowercase)
+            if self.exclude_files.contains(&name_lower) {
+                return false;
+            }
+
+            // 5. Exclude by extension (case-insensitive)
+            if extension
+                .as_ref()
+                .is_some_and(|ext| self.exclude_exts.contains(ext))
+            {
+                return false;
+            }
+        }
+
+        // 6. Exclude globs
+        !self.exclude.matched(path, false).is_ignore()
+    }
+}
+
 /// Finds files to include in the snapshot.
 /// `root` MUST be a canonicalized path for consistent strip_prefix behavior.
-pub fn find_files(root: &Path, config: &AppConfig) -> Result<DiscoveryResult> {
+/// With `changes`, only files git reports as changed are kept.
+pub fn find_files(
+    root: &Path,
+    config: &AppConfig,
+    changes: Option<&ChangeSet>,
+) -> Result<DiscoveryResult> {
     let mut files = Vec::new();
     let mut errors = Vec::new();
 
-    // Lowercase normalization for case-insensitive matching
-    let exclude_dirs: HashSet<String> = config
-        .exclude_dir
-        .iter()
-        .map(|s| s.to_lowercase())
-        .collect();
-    let exclude_files: HashSet<String> = config
-        .exclude_file
-        .iter()
-        .map(|s| s.to_lowercase())
-        .collect();
-    let exclude_exts: HashSet<String> = config
-        .exclude_ext
-        .iter()
-        .map(|s| s.to_lowercase())
-        .collect();
-
-    // Exclude system/hidden directories
-    let absolute_exclude_dirs: HashSet<&str> = [
-        ".git", ".ssh", ".aws", ".gnupg", ".kube", ".cargo", ".rustup",
-    ]
-    .into_iter()
-    .collect();
-
-    let regex = MERGED_REGEX.get_or_init(|| Regex::new(r"^merged_\d{8}_\d{6}\.md$").unwrap());
+    let filters = Filters::new(root, config)?;
 
-    let walker = WalkBuilder::new(root)
+    let mut builder = WalkBuilder::new(root);
+    if config.use_ctxsnapignore {
+        // Custom ignore files take precedence over .gitignore rules
+        builder.add_custom_ignore_filename(IGNORE_FILENAME);
+    }
+    let walker = builder
         .follow_links(false)
         .max_depth(Some(config.depth))
         .hidden(false)
@@ -56,17 +192,11 @@ pub fn find_files(root: &Path, config: &AppConfig) -> Result<DiscoveryResult> {
         .git_exclude(config.use_gitignore)
         .require_git(false) // Respect .gitignore even outside of a git repository
         .filter_entry({
-            let exclude_dirs = exclude_dirs.clone();
+            let filters = filters.clone();
             move |entry| {
                 // Never prune the root itself (depth 0)
                 if entry.depth() > 0 && entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false) {
-                    let name = entry.file_name().to_string_lossy();
-                    let name_lower = name.to_lowercase();
-                    if exclude_dirs.contains(&name_lower)
-                        || absolute_exclude_dirs.contains(name_lower.as_str())
-                    {
-                        return false;
-                    }
+                    return filters.keep_dir(entry.path());
                 }
                 true
             }
@@ -87,41 +217,16 @@ pub fn find_files(root: &Path, config: &AppConfig) -> Result<DiscoveryResult> {
                 }
 
                 let path = entry.path();
-                let name = entry.file_name().to_string_lossy();
-                let name_lower = name.to_lowercase(); // Normalize once
-
-                // 1. Snapshot outputs and internal config
-                if regex.is_match(&name) || name_lower == "ctxsnap.toml" {
+                if !filters.keep_file(path) {
                     continue;
                 }
 
-                // 2. Lockfiles
-                if !config.include_lockfiles && is_lockfile(&name) {
-                    continue;
-                }
-
-                // 3. Exclude files (check lowercase)
-                if exclude_files.contains(&name_lower) {
-                    continue;
-                }
-
-                // Secret prefixes
-                if name_lower.starts_with(".env")
-                    && !name_lower.ends_with(".example")
-                    && !name_lower.ends_with(".sample")
-                    && !name_lower.ends_with(".template")
-                    && name_lower != ".envrc"
-                {
-                    continue;
-                }
-
-                // 5. Exclude by extension (case-insensitive)
-                if path
-                    .extension()
-                    .and_then(|s| s.to_str())
-                    .is_some_and(|ext| exclude_exts.iter().any(|e| ext.eq_ignore_ascii_case(e)))
-                {
-                    continue;
+                // 7. Git change selection
+                if let Some(changes) = changes {
+                    let rel = crate::clean_path(path.strip_prefix(root).unwrap_or(path));
+                    if !changes.contains(&rel) {
+                        continue;
+                    }
                 }
 
                 files.push(path.to_path_buf());
@@ -132,14 +237,81 @@ pub fn find_files(root: &Path, config: &AppConfig) -> Result<DiscoveryResult> {
         }
     }
 
-    // Sort deterministically
+    sort_files(root, &mut files);
+    Ok(DiscoveryResult { files, errors })
+}
+
+/// Applies the same rules as `find_files` to the tree of a git revision.
+/// Returned paths are virtual: `root` joined with the path inside the tree.
+pub fn find_files_in_revision(
+    root: &Path,
+    config: &AppConfig,
+    revision: &Revision,
+) -> Result<DiscoveryResult> {
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+
+    let filters = Filters::new(root, config)?;
+
+    // Ignore files committed in the revision. .ctxsnapignore rules come first,
+    // and within each kind deeper files win, as in the filesystem walk.
+    let mut ignore_names = Vec::new();
+    if config.use_ctxsnapignore {
+        ignore_names.push(IGNORE_FILENAME);
+    }
+    if config.use_gitignore {
+        ignore_names.push(".gitignore");
+    }
+    let mut ignores = Vec::new();
+    for name in ignore_names {
+        let mut level = Vec::new();
+        for rel in revision.paths_named(name) {
+            let dir = root.join(Path::new(&rel).parent().unwrap_or(Path::new("")));
+            match revision
+                .read_path(&rel)
+                .and_then(|blob| parse_ignore(&dir, &rel, &blob))
+            {
+                Ok(gitignore) => level.push(gitignore),
+                Err(e) => errors.push(format!("{}: {:#}", rel, e)),
+            }
+        }
+        level.sort_by_key(|g| std::cmp::Reverse(g.path().components().count()));
+        ignores.extend(level);
+    }
+
+    for rel in revision.paths() {
+        let rel_path = Path::new(rel);
+        if rel_path.components().count() > config.depth {
+            continue;
+        }
+
+        // The walk prunes whole directories; check every ancestor instead
+        let path = root.join(rel_path);
+        let mut dirs = rel_path
+            .ancestors()
+            .skip(1)
+            .filter(|d| !d.as_os_str().is_empty());
+        if !dirs.all(|d| filters.keep_dir(&root.join(d))) {
+            continue;
+        }
+        if is_ignored(&ignores, &path) || !filters.keep_file(&path) {
+            continue;
+        }
+
+        files.push(path);
+    }
+
+    sort_files(root, &mut files);
+    Ok(DiscoveryResult { files, errors })
+}
+
+/// Sort deterministically
+fn sort_files(root: &Path, files: &mut [PathBuf]) {
     files.sort_by(|a, b| {
         let a_clean = crate::clean_path(a.strip_prefix(root).unwrap_or(a));
         let b_clean = crate::clean_path(b.strip_prefix(root).unwrap_or(b));
         a_clean.cmp(&b_clean)
     });
-
-    Ok(DiscoveryResult { files, errors })
 }
 
 fn is_lockfile(name: &str) -> bool {
@@ -153,3 +325,131 @@ fn is_lockfile(name: &str) -> bool {
     ];
     LOCKFILES.contains(&name)
 }
+
+/// Compiles gitignore-style patterns rooted at `root`.
+fn build_globs(root: &Path, patterns: &[String]) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
+    for pattern in patterns {
+        builder
+            .add_line(None, pattern)
+            .with_context(|| format!("{:?}", pattern))?;
+    }
+    Ok(builder.build()?)
+}
+
+/// Compiles an ignore file read from a revision. `dir` is the directory it applies to.
+fn parse_ignore(dir: &Path, rel: &str, blob: &[u8]) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(dir);
+    for line in String::from_utf8_lossy(blob).lines() {
+        builder.add_line(Some(PathBuf::from(rel)), line)?;
+    }
+    Ok(builder.build()?)
+}
+
+/// The first ignore file above `path` with a matching rule decides.
+fn is_ignored(ignores: &[Gitignore], path: &Path) -> bool {
+    for gitignore in ignores {
+        if !path.starts_with(gitignore.path()) {
+            continue;
+        }
+        match gitignore.matched_path_or_any_parents(path, false) {
+            Match::None => continue,
+            m => return m.is_ignore(),
+        }
+    }
+    false
+}
+
+/// Leading literal directory components of each anchored, non-negated pattern.
+/// `target/generated/*.rs` -> `["target", "generated"]`
+fn literal_prefixes(patterns: &[String]) -> Vec<Vec<String>> {
+    patterns
+        .iter()
+        .filter(|p| !p.starts_with('!'))
+        .filter_map(|p| {
+            let trimmed = p.trim_start_matches('/').trim_end_matches('/');
+            // Patterns without a separator match at any depth and name no directory
+            if !trimmed.contains('/') {
+                return None;
+            }
+            let prefix: Vec<String> = trimmed
+                .split('/')
+                .take_while(|c| !c.contains(['*', '?', '[', '\\']))
+                .map(|c| c.to_lowercase())
+                .collect();
+            (!prefix.is_empty()).then_some(prefix)
+        })
+        .collect()
+}
+
+/// An include pattern whose last component spells out a file name, such as
+/// `certs/server.key`, or an extension, such as `key` in `*.key`.
+#[derive(Clone)]
+struct NamingInclude {
+    glob: Gitignore,
+    name: Option<String>,
+    extension: Option<String>,
+}
+
+impl NamingInclude {
+    /// True if this pattern matches `path` and names its file name or extension.
+    fn names(&self, path: &Path, name_lower: &str, extension: Option<&str>) -> bool {
+        (self.name.as_deref() == Some(name_lower)
+            || extension.is_some_and(|ext| self.extension.as_deref() == Some(ext)))
+            && self
+                .glob
+                .matched_path_or_any_parents(path, false)
+                .is_ignore()
+    }
+}
+
+/// The non-negated include patterns that spell out a file name or extension.
+fn naming_includes(root: &Path, patterns: &[String]) -> Result<Vec<NamingInclude>> {
+    let literal = |s: &str| !s.contains(['*', '?', '[', '\\']);
+    let mut includes = Vec::new();
+    for pattern in patterns.iter().filter(|p| !p.starts_with('!')) {
+        let last = pattern
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        let name = literal(&last).then(|| last.clone());
+        let extension = last
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_string())
+            .filter(|ext| !ext.is_empty() && literal(ext));
+        if name.is_none() && extension.is_none() {
+            continue;
+        }
+        includes.push(NamingInclude {
+            glob: build_globs(root, std::slice::from_ref(pattern))
+                .context("Invalid include pattern")?,
+            name,
+            extension,
+        });
+    }
+    Ok(includes)
+}
+
+/// True if `rel_dir` lies on, or inside, the literal path of any include pattern.
+fn reaches_prefix(rel_dir: &Path, prefixes: &[Vec<String>]) -> bool {
+    let components: Vec<String> = rel_dir
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_lowercase())
+        .collect();
+    prefixes
+        .iter()
+        .any(|prefix| components.iter().zip(prefix.iter()).all(|(a, b)| a == b))
+}
+
+/// True if `rel_dir` lies inside the full literal path of any include pattern.
+fn within_prefix(rel_dir: &Path, prefixes: &[Vec<String>]) -> bool {
+    let components: Vec<String> = rel_dir
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_lowercase())
+        .collect();
+    prefixes.iter().any(|prefix| {
+        prefix.len() <= components.len() && prefix.iter().zip(&components).all(|(a, b)| a == b)
+    })
+}
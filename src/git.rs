@@ -0,0 +1,416 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::Mutex;
+
+// Field separators for `git log --format`
+const RECORD_START: char = '\x01';
+const FIELD_SEP: char = '\0';
+const LOG_FORMAT: &str = "--format=%x01%h%x00%aI%x00%s";
+
+/// Which git changes limit the snapshot.
+#[derive(Debug, Clone)]
+pub enum ChangeScope {
+    /// Working tree against the merge base of a ref and HEAD (committed and uncommitted).
+    Since(String),
+    /// Index against HEAD.
+    Staged,
+    /// Working tree against the index, plus untracked files.
+    Unstaged,
+}
+
+/// How diffs are emitted for changed files.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffMode {
+    /// Full content followed by the unified diff.
+    Append,
+    /// The unified diff instead of the full content.
+    Only,
+}
+
+/// Files git reports as changed, relative to the snapshot root.
+pub struct ChangeSet {
+    scope: ChangeScope,
+    // Resolved commit for `Since`
+    base: Option<String>,
+    // Forward-slash paths relative to the root
+    changed: HashSet<String>,
+    untracked: HashSet<String>,
+}
+
+impl ChangeSet {
+    /// Asks the local `git` binary which files under `root` changed.
+    /// Deleted files are skipped since there is nothing to read.
+    pub fn load(root: &Path, scope: ChangeScope) -> Result<Self> {
+        let base = match &scope {
+            ChangeScope::Since(rev) => {
+                let out = git(root, &["merge-base", rev, "HEAD"])
+                    .with_context(|| format!("Failed to resolve git ref {:?}", rev))?;
+                Some(String::from_utf8_lossy(&out).trim().to_string())
+            }
+            _ => None,
+        };
+
+        let mut diff_args = vec!["diff", "--name-only", "-z", "--relative", "--diff-filter=d"];
+        match &scope {
+            ChangeScope::Since(_) => diff_args.extend(base.as_deref()),
+            ChangeScope::Staged => diff_args.push("--cached"),
+            ChangeScope::Unstaged => {}
+        }
+        let changed = split_paths(&git(root, &diff_args)?);
+
+        let untracked = match scope {
+            ChangeScope::Staged => HashSet::new(),
+            _ => split_paths(&git(
+                root,
+                &["ls-files", "-z", "--others", "--exclude-standard"],
+            )?),
+        };
+
+        Ok(Self {
+            scope,
+            base,
+            changed,
+            untracked,
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        self.changed.len() + self.untracked.len()
+    }
+
+    pub fn contains(&self, rel_path: &str) -> bool {
+        self.changed.contains(rel_path) || self.untracked.contains(rel_path)
+    }
+
+    /// Human-readable description of the scope, e.g. `since main`.
+    pub fn describe(&self) -> String {
+        match &self.scope {
+            ChangeScope::Since(rev) => format!("since {}", rev),
+            ChangeScope::Staged => "staged".to_string(),
+            ChangeScope::Unstaged => "unstaged".to_string(),
+        }
+    }
+
+    /// Unified diff of one file. Untracked files are diffed against an empty file.
+    pub fn diff(&self, root: &Path, rel_path: &str) -> Result<String> {
+        let out = if self.untracked.contains(rel_path) {
+            // --no-index exits with 1 when the files differ
+            git_with_status(
+                root,
+                &[
+                    "diff",
+                    "--no-color",
+                    "--no-ext-diff",
+                    "--no-index",
+                    "--",
+                    "/dev/null",
+                    rel_path,
+                ],
+                &[0, 1],
+            )?
+        } else {
+            let mut args = vec!["diff", "--no-color", "--no-ext-diff", "--relative"];
+            match &self.scope {
+                ChangeScope::Since(_) => args.extend(self.base.as_deref()),
+                ChangeScope::Staged => args.push("--cached"),
+                ChangeScope::Unstaged => {}
+            }
+            args.extend(["--", rel_path]);
+            git(root, &args)?
+        };
+        Ok(String::from_utf8_lossy(&out).into_owned())
+    }
+}
+
+/// A commit as shown in the snapshot.
+#[derive(Serialize, Debug, Clone)]
+pub struct CommitSummary {
+    pub commit: String,
+    /// Author date, strict ISO 8601.
+    pub date: String,
+    pub subject: String,
+}
+
+impl CommitSummary {
+    /// Parses one `LOG_FORMAT` record without the leading marker.
+    fn parse(record: &str) -> Option<Self> {
+        let mut fields = record.splitn(3, FIELD_SEP);
+        Some(Self {
+            commit: fields.next()?.to_string(),
+            date: fields.next()?.to_string(),
+            subject: fields.next()?.trim_end().to_string(),
+        })
+    }
+}
+
+/// Revision the snapshot reflects.
+#[derive(Serialize, Debug, Clone)]
+pub struct RepoInfo {
+    /// HEAD, or the resolved commit under `--rev`.
+    pub head: String,
+    /// `None` on a detached HEAD and under `--rev`.
+    pub branch: Option<String>,
+    /// Tracked files differ from HEAD. `None` under `--rev`, where the working tree
+    /// is not what the snapshot shows.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dirty: Option<bool>,
+    pub recent_commits: Vec<CommitSummary>,
+}
+
+impl RepoInfo {
+    /// Describes `revision`, the commit resolved from `--rev`, or else the checked-out
+    /// HEAD with its branch and dirty state.
+    pub fn load(root: &Path, revision: Option<&str>, recent: usize) -> Result<Self> {
+        let rev = revision.unwrap_or("HEAD");
+        let log = git(root, &["log", &format!("-n{}", recent), LOG_FORMAT, rev])?;
+        let recent_commits = String::from_utf8_lossy(&log)
+            .split(RECORD_START)
+            .filter_map(CommitSummary::parse)
+            .collect();
+        if let Some(commit) = revision {
+            return Ok(Self {
+                head: commit.to_string(),
+                branch: None,
+                dirty: None,
+                recent_commits,
+            });
+        }
+
+        let head = git(root, &["rev-parse", "HEAD"])?;
+        // symbolic-ref exits with 1 on a detached HEAD
+        let branch = git_with_status(root, &["symbolic-ref", "--short", "-q", "HEAD"], &[0, 1])?;
+        let status = git(root, &["status", "--porcelain", "--untracked-files=no"])?;
+
+        let branch = String::from_utf8_lossy(&branch).trim().to_string();
+        Ok(Self {
+            head: String::from_utf8_lossy(&head).trim().to_string(),
+            branch: (!branch.is_empty()).then_some(branch),
+            dirty: Some(!status.is_empty()),
+            recent_commits,
+        })
+    }
+}
+
+/// Last commit up to `rev` touching each of `paths` (forward-slash, relative to `root`).
+/// Walks history once, newest first, and stops as soon as every path is found.
+/// Paths not tracked in `rev` are skipped, since no commit would ever end the walk.
+pub fn last_commits(
+    root: &Path,
+    rev: &str,
+    paths: &HashSet<String>,
+) -> Result<HashMap<String, CommitSummary>> {
+    let mut found = HashMap::new();
+    let tracked = split_paths(&git(root, &["ls-tree", "-r", "-z", "--name-only", rev])?);
+    let paths: HashSet<&String> = paths.intersection(&tracked).collect();
+    if paths.is_empty() {
+        return Ok(found);
+    }
+
+    let mut child = command(root)
+        .args(["-c", "core.quotepath=off", "log", LOG_FORMAT])
+        .args(["--name-only", "--relative", "--no-renames", rev, "--", "."])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to run git. Is it installed and on PATH?")?;
+    let stdout = child.stdout.take().context("git stdout not captured")?;
+
+    let mut current: Option<CommitSummary> = None;
+    for line in BufReader::new(stdout).lines() {
+        let line = line?;
+        if let Some(record) = line.strip_prefix(RECORD_START) {
+            current = CommitSummary::parse(record);
+        } else if let Some(commit) = &current {
+            if paths.contains(&line) && !found.contains_key(&line) {
+                found.insert(line, commit.clone());
+                if found.len() == paths.len() {
+                    break;
+                }
+            }
+        }
+    }
+
+    // History may be far from exhausted; stop the walk instead of draining it
+    let _ = child.kill();
+    let _ = child.wait();
+    Ok(found)
+}
+
+/// A blob in the tree of a revision.
+struct TreeEntry {
+    oid: String,
+    size: u64,
+}
+
+/// A committed tree, listed and read from the object database instead of the working tree.
+pub struct Revision {
+    /// The ref as given on the command line.
+    pub name: String,
+    /// Resolved commit id.
+    pub commit: String,
+    // Forward-slash paths relative to the root
+    entries: BTreeMap<String, TreeEntry>,
+    // Shared by processing workers; reads are serialized, decoding is not
+    reader: Mutex<BlobReader>,
+}
+
+impl Revision {
+    /// Resolves `name` and lists the blobs below `root`.
+    /// Symlinks and submodules are skipped, like symlinks in the filesystem walk.
+    pub fn load(root: &Path, name: &str) -> Result<Self> {
+        let spec = format!("{}^{{commit}}", name);
+        let commit = git(root, &["rev-parse", "--verify", "--quiet", &spec])
+            .with_context(|| format!("Unknown git revision {:?}", name))?;
+        let commit = String::from_utf8_lossy(&commit).trim().to_string();
+
+        // Paths are relative to `root` and limited to it
+        let listing = git(root, &["ls-tree", "-r", "-z", "--long", &commit])?;
+        let mut entries = BTreeMap::new();
+        for record in listing.split(|b| *b == 0).filter(|r| !r.is_empty()) {
+            // <mode> SP <type> SP <oid> SP+ <size> TAB <path>
+            let record = String::from_utf8_lossy(record);
+            let Some((meta, path)) = record.split_once('\t') else {
+                continue;
+            };
+            let fields: Vec<&str> = meta.split_whitespace().collect();
+            if let [mode, "blob", oid, size] = fields[..] {
+                if mode == "120000" {
+                    continue;
+                }
+                entries.insert(
+                    path.to_string(),
+                    TreeEntry {
+                        oid: oid.to_string(),
+                        size: size.parse().unwrap_or(0),
+                    },
+                );
+            }
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            commit,
+            entries,
+            reader: Mutex::new(BlobReader::spawn(root)?),
+        })
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    /// Paths whose file name is `name`, e.g. every `.gitignore`.
+    pub fn paths_named(&self, name: &str) -> Vec<String> {
+        self.paths()
+            .filter(|p| p.rsplit('/').next() == Some(name))
+            .map(String::from)
+            .collect()
+    }
+
+    pub fn size(&self, rel_path: &str) -> Option<u64> {
+        self.entries.get(rel_path).map(|e| e.size)
+    }
+
+    /// Reads the blob at `rel_path`.
+    pub fn read_path(&self, rel_path: &str) -> Result<Vec<u8>> {
+        let entry = self
+            .entries
+            .get(rel_path)
+            .with_context(|| format!("{} is not in {}", rel_path, self.name))?;
+        self.reader
+            .lock()
+            .map_err(|_| anyhow::anyhow!("git blob reader poisoned"))?
+            .read(&entry.oid)
+    }
+}
+
+/// A long-running `git cat-file --batch`, so reading a blob costs no process spawn.
+struct BlobReader {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl BlobReader {
+    fn spawn(root: &Path) -> Result<Self> {
+        let mut child = command(root)
+            .args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Failed to run git. Is it installed and on PATH?")?;
+        let stdin = child.stdin.take().context("git stdin not captured")?;
+        let stdout = child.stdout.take().context("git stdout not captured")?;
+        Ok(Self {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+        })
+    }
+
+    fn read(&mut self, oid: &str) -> Result<Vec<u8>> {
+        writeln!(self.stdin, "{}", oid)?;
+        self.stdin.flush()?;
+
+        // <oid> SP <type> SP <size> LF <contents> LF, or <oid> SP missing LF
+        let mut header = String::new();
+        self.stdout.read_line(&mut header)?;
+        let size = match header.split_whitespace().collect::<Vec<_>>()[..] {
+            [_, "blob", size] => size
+                .parse::<usize>()
+                .context("Malformed git cat-file header")?,
+            _ => anyhow::bail!("Object {} unavailable: {}", oid, header.trim()),
+        };
+
+        let mut content = vec![0; size + 1];
+        self.stdout.read_exact(&mut content)?;
+        content.pop();
+        Ok(content)
+    }
+}
+
+impl Drop for BlobReader {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn split_paths(out: &[u8]) -> HashSet<String> {
+    out.split(|b| *b == 0)
+        .filter(|p| !p.is_empty())
+        .map(|p| String::from_utf8_lossy(p).into_owned())
+        .collect()
+}
+
+fn git(root: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    git_with_status(root, args, &[0])
+}
+
+fn command(root: &Path) -> Command {
+    let mut cmd = Command::new("git");
+    cmd.arg("--literal-pathspecs").arg("-C").arg(root);
+    cmd
+}
+
+/// Runs `git` in `root` and returns stdout, treating the listed exit codes as success.
+fn git_with_status(root: &Path, args: &[&str], ok_codes: &[i32]) -> Result<Vec<u8>> {
+    let output = command(root)
+        .args(args)
+        .output()
+        .context("Failed to run git. Is it installed and on PATH?")?;
+
+    let code = output.status.code().unwrap_or(-1);
+    anyhow::ensure!(
+        ok_codes.contains(&code),
+        "git {} failed: {}",
+        args.first().unwrap_or(&""),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(output.stdout)
+}
@@ -0,0 +1,360 @@
+use crate::comments::{self, CommentMode};
+use crate::language;
+use crate::parallel;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Extensions tried, in order, for a JS/TS specifier without one.
+const JS_EXTENSIONS: &[&str] = &["ts", "tsx", "mts", "js", "jsx", "mjs", "cjs", "json", "vue"];
+
+static RUST_MOD: OnceLock<Regex> = OnceLock::new();
+static RUST_USE: OnceLock<Regex> = OnceLock::new();
+static PY_IMPORT: OnceLock<Regex> = OnceLock::new();
+static PY_FROM: OnceLock<Regex> = OnceLock::new();
+static JS_FROM: OnceLock<Regex> = OnceLock::new();
+static JS_CALL: OnceLock<Regex> = OnceLock::new();
+static C_INCLUDE: OnceLock<Regex> = OnceLock::new();
+
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).expect("import patterns are valid"))
+}
+
+/// Files reachable from `seeds` over imports resolved within `files`, ordered by
+/// import distance and then by their order in `files`. `depth` limits the number of
+/// hops; `read` returns a file's text, or `None` for files that cannot be scanned.
+pub fn reachable(
+    files: Vec<PathBuf>,
+    root: &Path,
+    seeds: &[usize],
+    depth: Option<usize>,
+    jobs: usize,
+    read: impl Fn(&Path) -> Option<String> + Sync,
+) -> Vec<PathBuf> {
+    let rels: Vec<String> = files
+        .iter()
+        .map(|p| crate::clean_path(p.strip_prefix(root).unwrap_or(p)))
+        .collect();
+    let index: HashMap<&str, usize> = rels
+        .iter()
+        .enumerate()
+        .map(|(idx, rel)| (rel.as_str(), idx))
+        .collect();
+    let exists = |rel: &str| index.contains_key(rel);
+
+    let mut seen: HashSet<usize> = HashSet::new();
+    let mut level: Vec<usize> = seeds.iter().copied().filter(|&s| seen.insert(s)).collect();
+    let mut order = Vec::new();
+    let mut distance = 0;
+    while !level.is_empty() {
+        order.extend_from_slice(&level);
+        if depth.is_some_and(|max| distance >= max) {
+            break;
+        }
+        let found = parallel::map_ordered(level, jobs, |idx| match read(&files[idx]) {
+            Some(content) => imports(&rels[idx], &files[idx], &content, &exists),
+            None => Vec::new(),
+        });
+        let mut next: Vec<usize> = found
+            .into_iter()
+            .flatten()
+            .filter_map(|rel| index.get(rel.as_str()).copied())
+            .filter(|&idx| seen.insert(idx))
+            .collect();
+        next.sort_unstable();
+        level = next;
+        distance += 1;
+    }
+
+    let mut files: Vec<Option<PathBuf>> = files.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .filter_map(|idx| files[idx].take())
+        .collect()
+}
+
+/// Relative paths of the files that `rel` imports, for the languages with a resolver.
+fn imports(rel: &str, path: &Path, content: &str, exists: &dyn Fn(&str) -> bool) -> Vec<String> {
+    let Some(language) = language::detect(path, content).map(|l| l.name) else {
+        return Vec::new();
+    };
+    // Commented-out imports are not dependencies
+    let stripped;
+    let content = match comments::syntax_for(language) {
+        Some(syntax) => {
+            stripped = comments::strip(content, syntax, CommentMode::All).0;
+            &stripped
+        }
+        None => content,
+    };
+    match language {
+        "rust" => rust_imports(rel, content, exists),
+        "python" => python_imports(rel, content, exists),
+        "javascript" | "jsx" | "typescript" | "tsx" | "vue" | "svelte" => {
+            js_imports(rel, content, exists)
+        }
+        "c" | "cpp" => c_includes(rel, content, exists),
+        _ => Vec::new(),
+    }
+}
+
+/// `mod foo;` declarations and `use` paths under `crate`, `self`, `super` or a child module.
+fn rust_imports(rel: &str, content: &str, exists: &dyn Fn(&str) -> bool) -> Vec<String> {
+    let dir = parent(rel);
+    let stem = rel
+        .rsplit('/')
+        .next()
+        .unwrap_or(rel)
+        .trim_end_matches(".rs");
+    let crate_dir = ancestors(dir)
+        .find(|d| exists(&join(d, "lib.rs")) || exists(&join(d, "main.rs")))
+        .unwrap_or(dir);
+    // Directory holding the files of this module's children
+    let module_dir = if dir == crate_dir || matches!(stem, "mod" | "lib" | "main") {
+        dir.to_string()
+    } else {
+        join(dir, stem)
+    };
+    let module_file = |path: &str| {
+        [format!("{}.rs", path), format!("{}/mod.rs", path)]
+            .into_iter()
+            .find(|c| exists(c))
+    };
+
+    let mut out = Vec::new();
+    let mod_re = regex(
+        &RUST_MOD,
+        r"(?m)^\s*(?:pub(?:\([^)]*\))?\s+)?mod\s+([A-Za-z_][A-Za-z0-9_]*)\s*;",
+    );
+    for cap in mod_re.captures_iter(content) {
+        out.extend(module_file(&join(&module_dir, &cap[1])));
+    }
+
+    let use_re = regex(
+        &RUST_USE,
+        r"(?m)^\s*(?:pub(?:\([^)]*\))?\s+)?use\s+([^;]+);",
+    );
+    for cap in use_re.captures_iter(content) {
+        let mut paths = Vec::new();
+        use_paths("", &cap[1], &mut paths);
+        for path in paths {
+            let mut segments: Vec<&str> = path
+                .split("::")
+                .filter(|s| !s.is_empty() && *s != "*" && *s != "self")
+                .collect();
+            let mut base = module_dir.clone();
+            if segments.first() == Some(&"crate") {
+                segments.remove(0);
+                base = crate_dir.to_string();
+            }
+            while segments.first() == Some(&"super") {
+                segments.remove(0);
+                base = parent(&base).to_string();
+            }
+            // The longest prefix naming a module file, e.g. `a::b` for `crate::a::b::Item`
+            out.extend(
+                (1..=segments.len())
+                    .rev()
+                    .find_map(|n| module_file(&join(&base, &segments[..n].join("/")))),
+            );
+        }
+    }
+    out
+}
+
+/// Flattens a `use` tree such as `a::{b, c::{d as e, self}}` into `a::b`, `a::c::d`, `a::c`.
+fn use_paths(prefix: &str, tree: &str, out: &mut Vec<String>) {
+    let tree: String = tree.split_whitespace().collect::<Vec<_>>().join(" ");
+    let tree = tree.trim();
+    let join_path = |base: &str| {
+        let base = base.trim().trim_end_matches("::").replace(' ', "");
+        match (prefix.is_empty(), base.is_empty()) {
+            (true, _) => base,
+            (false, true) => prefix.to_string(),
+            (false, false) => format!("{}::{}", prefix, base),
+        }
+    };
+    match tree.find('{') {
+        Some(open) => {
+            let prefix = join_path(&tree[..open]);
+            let inner = tree[open + 1..]
+                .strip_suffix('}')
+                .unwrap_or(&tree[open + 1..]);
+            let mut nesting = 0;
+            let mut start = 0;
+            for (at, c) in inner.char_indices() {
+                match c {
+                    '{' => nesting += 1,
+                    '}' => nesting -= 1,
+                    ',' if nesting == 0 => {
+                        use_paths(&prefix, &inner[start..at], out);
+                        start = at + 1;
+                    }
+                    _ => {}
+                }
+            }
+            use_paths(&prefix, &inner[start..], out);
+        }
+        None if tree.is_empty() => {}
+        None => out.push(join_path(tree.split(" as ").next().unwrap_or(tree))),
+    }
+}
+
+/// `import a.b` and `from a import b` statements, relative ones included. Absolute
+/// modules are looked up from the importing file's directory up to the root.
+fn python_imports(rel: &str, content: &str, exists: &dyn Fn(&str) -> bool) -> Vec<String> {
+    let dir = parent(rel);
+    let module_file = |base: &str, module: &str| {
+        let path = join(base, &module.replace('.', "/"));
+        [format!("{}.py", path), format!("{}/__init__.py", path)]
+            .into_iter()
+            .find(|c| exists(c))
+    };
+    let absolute = |module: &str| ancestors(dir).find_map(|base| module_file(base, module));
+
+    let mut out = Vec::new();
+    let import_re = regex(&PY_IMPORT, r"(?m)^[ \t]*import[ \t]+([\w. \t,]+)$");
+    for cap in import_re.captures_iter(content) {
+        for item in cap[1].split(',') {
+            if let Some(module) = item.split_whitespace().next() {
+                out.extend(absolute(module));
+            }
+        }
+    }
+
+    let from_re = regex(
+        &PY_FROM,
+        r"(?m)^[ \t]*from[ \t]+(\.*)([\w.]*)[ \t]+import[ \t]+(\([^)]*\)|[^\n]+)",
+    );
+    for cap in from_re.captures_iter(content) {
+        let (dots, module) = (cap[1].len(), &cap[2]);
+        let names: Vec<&str> = cap[3]
+            .trim_matches(|c: char| c == '(' || c == ')' || c.is_whitespace())
+            .split(',')
+            .filter_map(|item| item.split_whitespace().next())
+            .filter(|name| *name != "*")
+            .collect();
+        let resolve = |module: &str| match dots {
+            0 => absolute(module),
+            _ => {
+                let base = (1..dots).fold(dir, |d, _| parent(d));
+                module_file(base, module)
+                    .or_else(|| module.is_empty().then(|| join(base, "__init__.py")))
+                    .filter(|c| exists(c))
+            }
+        };
+        for name in names {
+            // `from pkg import mod` imports a submodule when one exists, else names in `pkg`
+            let submodule = if module.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}.{}", module, name)
+            };
+            out.extend(resolve(&submodule).or_else(|| resolve(module)));
+        }
+    }
+    out
+}
+
+/// Relative `import`/`export ... from`, side-effect `import` and `require()` specifiers.
+/// Package imports are outside the repository and are not followed.
+fn js_imports(rel: &str, content: &str, exists: &dyn Fn(&str) -> bool) -> Vec<String> {
+    let dir = parent(rel);
+    let from_re = regex(
+        &JS_FROM,
+        r#"\b(?:import|export)\b(?:[^;'"`]*?\bfrom)?\s*['"]([^'"\n]+)['"]"#,
+    );
+    let call_re = regex(
+        &JS_CALL,
+        r#"\b(?:require|import)\s*\(\s*['"]([^'"\n]+)['"]\s*\)"#,
+    );
+    let mut out = Vec::new();
+    for cap in from_re
+        .captures_iter(content)
+        .chain(call_re.captures_iter(content))
+    {
+        let spec = &cap[1];
+        let path = match spec.strip_prefix('/') {
+            Some(absolute) => normalize(absolute),
+            None if spec.starts_with('.') => normalize(&join(dir, spec)),
+            None => None,
+        };
+        let Some(path) = path else {
+            continue;
+        };
+        // TypeScript sources are imported by the name of their compiled `.js` output
+        let stem = [".js", ".jsx", ".mjs", ".cjs"]
+            .iter()
+            .find_map(|ext| path.strip_suffix(ext));
+        let mut candidates = vec![path.clone()];
+        for base in [Some(path.as_str()), stem].into_iter().flatten() {
+            candidates.extend(JS_EXTENSIONS.iter().map(|ext| format!("{}.{}", base, ext)));
+        }
+        candidates.extend(
+            JS_EXTENSIONS
+                .iter()
+                .map(|ext| format!("{}/index.{}", path, ext)),
+        );
+        out.extend(candidates.into_iter().find(|c| exists(c)));
+    }
+    out
+}
+
+/// `#include` targets, looked up next to the including file, then in each directory
+/// up to the root and its `include/` subdirectory.
+fn c_includes(rel: &str, content: &str, exists: &dyn Fn(&str) -> bool) -> Vec<String> {
+    let dir = parent(rel);
+    let include_re = regex(
+        &C_INCLUDE,
+        r#"(?m)^[ \t]*#[ \t]*include[ \t]*[<"]([^>"\n]+)[>"]"#,
+    );
+    include_re
+        .captures_iter(content)
+        .filter_map(|cap| {
+            let spec = cap[1].trim();
+            ancestors(dir)
+                .flat_map(|base| [join(base, spec), join(&join(base, "include"), spec)])
+                .filter_map(|c| normalize(&c))
+                .find(|c| exists(c))
+        })
+        .collect()
+}
+
+/// Directory part of a relative path, `""` at the root.
+fn parent(rel: &str) -> &str {
+    rel.rfind('/').map_or("", |at| &rel[..at])
+}
+
+/// `dir` and each of its parents up to the root (`""`).
+fn ancestors(dir: &str) -> impl Iterator<Item = &str> {
+    let mut next = Some(dir);
+    std::iter::from_fn(move || {
+        let current = next?;
+        next = (!current.is_empty()).then(|| parent(current));
+        Some(current)
+    })
+}
+
+fn join(dir: &str, rest: &str) -> String {
+    if dir.is_empty() {
+        rest.to_string()
+    } else {
+        format!("{}/{}", dir, rest)
+    }
+}
+
+/// Resolves `.` and `..` segments; `None` for paths leaving the root.
+fn normalize(path: &str) -> Option<String> {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
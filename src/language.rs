@@ -0,0 +1,519 @@
+use std::path::Path;
+
+/// A language recognized from a file's name, extension, modeline or shebang.
+#[derive(Debug)]
+pub struct Language {
+    /// Canonical name, used as the fence info string and in the composition stats.
+    pub name: &'static str,
+    /// Lowercase extensions without the dot.
+    extensions: &'static [&'static str],
+    /// Exact file names; a trailing `*` matches any suffix (`Dockerfile.*`).
+    filenames: &'static [&'static str],
+    /// Shebang interpreters, without version suffixes (`python` matches `python3.12`).
+    interpreters: &'static [&'static str],
+    /// Other names used by Vim and Emacs modelines.
+    aliases: &'static [&'static str],
+}
+
+const BASE: Language = Language {
+    name: "",
+    extensions: &[],
+    filenames: &[],
+    interpreters: &[],
+    aliases: &[],
+};
+
+/// Known languages. Earlier entries win when an extension or alias is listed twice.
+const LANGUAGES: &[Language] = &[
+    Language {
+        name: "rust",
+        extensions: &["rs"],
+        ..BASE
+    },
+    Language {
+        name: "c",
+        extensions: &["c", "h"],
+        ..BASE
+    },
+    Language {
+        name: "cpp",
+        extensions: &["cpp", "cc", "cxx", "c++", "hpp", "hh", "hxx", "h++", "ipp"],
+        aliases: &["c++"],
+        ..BASE
+    },
+    Language {
+        name: "csharp",
+        extensions: &["cs", "csx"],
+        aliases: &["c#"],
+        ..BASE
+    },
+    Language {
+        name: "go",
+        extensions: &["go"],
+        aliases: &["golang"],
+        ..BASE
+    },
+    Language {
+        name: "java",
+        extensions: &["java"],
+        ..BASE
+    },
+    Language {
+        name: "kotlin",
+        extensions: &["kt", "kts"],
+        ..BASE
+    },
+    Language {
+        name: "swift",
+        extensions: &["swift"],
+        ..BASE
+    },
+    Language {
+        name: "scala",
+        extensions: &["scala", "sc"],
+        ..BASE
+    },
+    Language {
+        name: "javascript",
+        extensions: &["js", "mjs", "cjs"],
+        interpreters: &["node", "nodejs"],
+        ..BASE
+    },
+    Language {
+        name: "jsx",
+        extensions: &["jsx"],
+        ..BASE
+    },
+    Language {
+        name: "typescript",
+        extensions: &["ts", "mts", "cts"],
+        interpreters: &["deno", "ts-node", "tsx", "bun"],
+        ..BASE
+    },
+    Language {
+        name: "tsx",
+        extensions: &["tsx"],
+        ..BASE
+    },
+    Language {
+        name: "css",
+        extensions: &["css"],
+        ..BASE
+    },
+    Language {
+        name: "scss",
+        extensions: &["scss"],
+        ..BASE
+    },
+    Language {
+        name: "less",
+        extensions: &["less"],
+        ..BASE
+    },
+    Language {
+        name: "php",
+        extensions: &["php", "phtml"],
+        interpreters: &["php"],
+        ..BASE
+    },
+    Language {
+        name: "python",
+        extensions: &["py", "pyi", "pyw"],
+        filenames: &["SConstruct", "SConscript"],
+        interpreters: &["python", "pypy"],
+        ..BASE
+    },
+    Language {
+        name: "bash",
+        extensions: &["sh", "bash"],
+        filenames: &[".bashrc", ".bash_profile", ".bash_aliases", ".profile"],
+        interpreters: &["sh", "bash", "dash", "ksh", "ash"],
+        aliases: &["shell", "shell-script", "shellscript"],
+    },
+    Language {
+        name: "zsh",
+        extensions: &["zsh"],
+        filenames: &[".zshrc", ".zshenv", ".zprofile"],
+        interpreters: &["zsh"],
+        ..BASE
+    },
+    Language {
+        name: "ruby",
+        extensions: &["rb", "rake", "gemspec"],
+        filenames: &["Gemfile", "Rakefile", "Vagrantfile", "Podfile", "Guardfile"],
+        interpreters: &["ruby"],
+        ..BASE
+    },
+    Language {
+        name: "perl",
+        extensions: &["pl", "pm"],
+        interpreters: &["perl"],
+        ..BASE
+    },
+    Language {
+        name: "powershell",
+        extensions: &["ps1", "psm1", "psd1"],
+        interpreters: &["pwsh", "powershell"],
+        aliases: &["ps"],
+        ..BASE
+    },
+    Language {
+        name: "yaml",
+        extensions: &["yaml", "yml"],
+        ..BASE
+    },
+    Language {
+        name: "toml",
+        extensions: &["toml"],
+        filenames: &["Pipfile"],
+        ..BASE
+    },
+    Language {
+        name: "sql",
+        extensions: &["sql"],
+        ..BASE
+    },
+    Language {
+        name: "lua",
+        extensions: &["lua"],
+        interpreters: &["lua", "luajit"],
+        ..BASE
+    },
+    Language {
+        name: "haskell",
+        extensions: &["hs"],
+        interpreters: &["runghc", "runhaskell"],
+        ..BASE
+    },
+    Language {
+        name: "dart",
+        extensions: &["dart"],
+        ..BASE
+    },
+    Language {
+        name: "groovy",
+        extensions: &["groovy", "gradle"],
+        filenames: &["Jenkinsfile"],
+        ..BASE
+    },
+    Language {
+        name: "protobuf",
+        extensions: &["proto"],
+        ..BASE
+    },
+    Language {
+        name: "r",
+        extensions: &["r"],
+        interpreters: &["Rscript"],
+        ..BASE
+    },
+    Language {
+        name: "cmake",
+        extensions: &["cmake"],
+        filenames: &["CMakeLists.txt"],
+        ..BASE
+    },
+    Language {
+        name: "nim",
+        extensions: &["nim"],
+        ..BASE
+    },
+    Language {
+        name: "ada",
+        extensions: &["ada", "adb", "ads"],
+        ..BASE
+    },
+    Language {
+        name: "vhdl",
+        extensions: &["vhd", "vhdl"],
+        ..BASE
+    },
+    Language {
+        name: "html",
+        extensions: &["html", "htm", "xhtml"],
+        ..BASE
+    },
+    Language {
+        name: "xml",
+        extensions: &["xml", "xsd", "xsl", "xslt", "plist", "csproj"],
+        ..BASE
+    },
+    Language {
+        name: "vue",
+        extensions: &["vue"],
+        ..BASE
+    },
+    Language {
+        name: "svelte",
+        extensions: &["svelte"],
+        ..BASE
+    },
+    Language {
+        name: "makefile",
+        extensions: &["mk", "mak"],
+        filenames: &["Makefile", "makefile", "GNUmakefile", "Makefile.*"],
+        interpreters: &["make"],
+        aliases: &["make"],
+    },
+    Language {
+        name: "dockerfile",
+        extensions: &["dockerfile"],
+        filenames: &[
+            "Dockerfile",
+            "Containerfile",
+            "Dockerfile.*",
+            "Containerfile.*",
+        ],
+        aliases: &["docker"],
+        ..BASE
+    },
+    Language {
+        name: "markdown",
+        extensions: &["md", "markdown"],
+        ..BASE
+    },
+    Language {
+        name: "json",
+        extensions: &["json"],
+        ..BASE
+    },
+    Language {
+        name: "jsonc",
+        extensions: &["jsonc"],
+        ..BASE
+    },
+    Language {
+        name: "diff",
+        extensions: &["diff", "patch"],
+        ..BASE
+    },
+    Language {
+        name: "ini",
+        extensions: &["ini", "cfg"],
+        filenames: &[".editorconfig", ".gitconfig"],
+        aliases: &["dosini", "conf"],
+        ..BASE
+    },
+    Language {
+        name: "dotenv",
+        filenames: &[".env", ".env.*"],
+        ..BASE
+    },
+    Language {
+        name: "gitignore",
+        filenames: &[".gitignore", ".dockerignore", ".ctxsnapignore"],
+        ..BASE
+    },
+    Language {
+        name: "elixir",
+        extensions: &["ex", "exs"],
+        interpreters: &["elixir"],
+        ..BASE
+    },
+    Language {
+        name: "erlang",
+        extensions: &["erl", "hrl"],
+        interpreters: &["escript"],
+        ..BASE
+    },
+    Language {
+        name: "clojure",
+        extensions: &["clj", "cljs", "cljc", "edn"],
+        ..BASE
+    },
+    Language {
+        name: "ocaml",
+        extensions: &["ml", "mli"],
+        ..BASE
+    },
+    Language {
+        name: "fsharp",
+        extensions: &["fs", "fsi", "fsx"],
+        aliases: &["f#"],
+        ..BASE
+    },
+    Language {
+        name: "zig",
+        extensions: &["zig"],
+        ..BASE
+    },
+    Language {
+        name: "julia",
+        extensions: &["jl"],
+        interpreters: &["julia"],
+        ..BASE
+    },
+    Language {
+        name: "hcl",
+        extensions: &["tf", "tfvars", "hcl"],
+        aliases: &["terraform"],
+        ..BASE
+    },
+    Language {
+        name: "graphql",
+        extensions: &["graphql", "gql"],
+        ..BASE
+    },
+    Language {
+        name: "nix",
+        extensions: &["nix"],
+        ..BASE
+    },
+    Language {
+        name: "latex",
+        extensions: &["tex", "sty", "cls"],
+        aliases: &["tex"],
+        ..BASE
+    },
+    Language {
+        name: "batch",
+        extensions: &["bat", "cmd"],
+        aliases: &["dosbatch"],
+        ..BASE
+    },
+    Language {
+        name: "vim",
+        extensions: &["vim"],
+        filenames: &[".vimrc"],
+        ..BASE
+    },
+    Language {
+        name: "tcl",
+        extensions: &["tcl"],
+        interpreters: &["tclsh", "wish"],
+        ..BASE
+    },
+    Language {
+        name: "awk",
+        extensions: &["awk"],
+        interpreters: &["awk", "gawk", "mawk"],
+        ..BASE
+    },
+    Language {
+        name: "text",
+        extensions: &["txt", "text"],
+        ..BASE
+    },
+];
+
+/// Modelines are looked for in this many lines at the start and end of a file, like Vim.
+const MODELINE_LINES: usize = 5;
+
+/// Words in a `.h` file that only C++ uses.
+const CPP_MARKERS: &[&str] = &[
+    "namespace ",
+    "template <",
+    "template<",
+    "class ",
+    "std::",
+    "public:",
+    "private:",
+    "protected:",
+    "constexpr ",
+    "nullptr",
+];
+
+/// The language of a file: an exact file name first, then a Vim or Emacs modeline, then
+/// the extension, then the `#!` interpreter. `.h` headers using C++ features are C++.
+pub fn detect(path: &Path, content: &str) -> Option<&'static Language> {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    if let Some(language) = LANGUAGES
+        .iter()
+        .find(|l| l.filenames.iter().any(|f| name_matches(name, f)))
+    {
+        return Some(language);
+    }
+    if let Some(language) = modeline(content) {
+        return Some(language);
+    }
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase);
+    if let Some(language) = ext.as_deref().and_then(by_extension) {
+        if ext.as_deref() == Some("h") && CPP_MARKERS.iter().any(|m| content.contains(m)) {
+            return by_extension("cpp");
+        }
+        return Some(language);
+    }
+    shebang(content)
+}
+
+fn name_matches(name: &str, pattern: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix) && name.len() > prefix.len(),
+        None => name == pattern,
+    }
+}
+
+fn by_extension(ext: &str) -> Option<&'static Language> {
+    LANGUAGES.iter().find(|l| l.extensions.contains(&ext))
+}
+
+/// A language by canonical name, extension or modeline alias, ignoring case.
+fn by_alias(alias: &str) -> Option<&'static Language> {
+    let alias = alias.to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|l| l.name == alias || l.aliases.contains(&alias.as_str()))
+        .or_else(|| by_extension(&alias))
+}
+
+/// `#!/usr/bin/python3`, `#!/usr/bin/env bash` or `#!/usr/bin/env -S node --flag`.
+fn shebang(content: &str) -> Option<&'static Language> {
+    let line = content.lines().next()?.strip_prefix("#!")?;
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        // Skip `env` options and variable assignments
+        program = words.find(|w| !w.starts_with('-') && !w.contains('='))?;
+    }
+    let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    LANGUAGES.iter().find(|l| l.interpreters.contains(&program))
+}
+
+/// `vim: set ft=python:`, `vi: filetype=sh` or `-*- mode: c++ -*-` near either end of the file.
+fn modeline(content: &str) -> Option<&'static Language> {
+    let head = content.lines().take(MODELINE_LINES);
+    let tail = content.lines().rev().take(MODELINE_LINES);
+    head.chain(tail)
+        .find_map(|line| vim_filetype(line).or_else(|| emacs_mode(line)))
+        .and_then(by_alias)
+}
+
+fn vim_filetype(line: &str) -> Option<&str> {
+    let start = ["vim:", "vi:", "ex:"]
+        .iter()
+        .filter_map(|marker| {
+            let at = line.find(marker)?;
+            // The marker starts a word, so `navi:` is not one
+            let word_start = !line[..at].ends_with(|c: char| c.is_alphanumeric());
+            word_start.then_some(at + marker.len())
+        })
+        .min()?;
+    line[start..]
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .find_map(|option| {
+            ["ft=", "filetype=", "syntax=", "syn="]
+                .iter()
+                .find_map(|key| option.strip_prefix(key))
+        })
+        .filter(|ft| !ft.is_empty())
+}
+
+fn emacs_mode(line: &str) -> Option<&str> {
+    let start = line.find("-*-")? + 3;
+    let end = start + line[start..].find("-*-")?;
+    let vars = line[start..end].trim();
+    let mode = if vars.contains(':') {
+        vars.split(';').find_map(|var| {
+            let (key, value) = var.split_once(':')?;
+            key.trim()
+                .eq_ignore_ascii_case("mode")
+                .then_some(value.trim())
+        })?
+    } else {
+        vars
+    };
+    Some(mode.trim_end_matches("-mode")).filter(|m| !m.is_empty())
+}
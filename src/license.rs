@@ -0,0 +1,299 @@
+use crate::comments::{self, Syntax};
+use crate::lines;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// What happens to a license or copyright header at the top of a file.
+#[derive(Serialize, Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum LicenseMode {
+    /// Leave license headers in place.
+    #[default]
+    Keep,
+    /// Remove license headers.
+    Remove,
+    /// Replace each license header with a one-line `SPDX: <id>` comment.
+    Spdx,
+    /// Remove license headers and list each distinct one once in the snapshot header.
+    Header,
+}
+
+/// Words that mark a leading comment block as a license header.
+const LICENSE_MARKERS: &[&str] = &[
+    "copyright",
+    "license",
+    "licence",
+    "spdx-license-identifier",
+    "all rights reserved",
+];
+
+/// Unmarked leading blocks count as headers when similar blocks lead this many files.
+const REPEAT_MIN: usize = 3;
+
+/// Shorter unmarked blocks are never treated as headers, however often they repeat.
+const MIN_WORDS: usize = 8;
+
+/// Word-set (Jaccard) similarity at which two blocks are the same header,
+/// so that years, file names and author lists may differ.
+const SIMILARITY: f64 = 0.8;
+
+/// Distinct unmarked blocks tracked by `Headers::scan`, which bounds its cost on large trees.
+const SCAN_GROUPS: usize = 256;
+
+/// Phrases identifying common license texts that lack an `SPDX-License-Identifier` line.
+/// The first match wins, so more specific phrases come first.
+const KNOWN_LICENSES: &[(&[&str], &str)] = &[
+    (&["apache license", "version 2.0"], "Apache-2.0"),
+    (&["permission is hereby granted, free of charge"], "MIT"),
+    (
+        &["gnu lesser general public license", "version 3"],
+        "LGPL-3.0",
+    ),
+    (
+        &["gnu lesser general public license", "version 2.1"],
+        "LGPL-2.1",
+    ),
+    (&["gnu affero general public license"], "AGPL-3.0"),
+    (&["gnu general public license", "version 3"], "GPL-3.0"),
+    (&["gnu general public license", "version 2"], "GPL-2.0"),
+    (&["mozilla public license", "2.0"], "MPL-2.0"),
+    (
+        &[
+            "redistribution and use in source and binary forms",
+            "neither the name",
+        ],
+        "BSD-3-Clause",
+    ),
+    (
+        &["redistribution and use in source and binary forms"],
+        "BSD-2-Clause",
+    ),
+    (
+        &["permission to use, copy, modify, and or distribute"],
+        "ISC",
+    ),
+    (&["this is free and unencumbered software"], "Unlicense"),
+];
+
+pub fn has_marker(text: &str) -> bool {
+    let text = text.to_lowercase();
+    LICENSE_MARKERS.iter().any(|marker| text.contains(marker))
+}
+
+/// Lowercase words of a block, ignoring comment markers, punctuation and numbers such as years.
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn similar(a: &HashSet<String>, b: &HashSet<String>) -> bool {
+    let (small, large) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    // Jaccard cannot reach the threshold when the sizes differ too much
+    if (small.len() as f64) < SIMILARITY * large.len() as f64 {
+        return false;
+    }
+    let common = small.iter().filter(|w| large.contains(*w)).count();
+    let union = a.len() + b.len() - common;
+    union > 0 && common as f64 >= SIMILARITY * union as f64
+}
+
+/// A header text and the number of files it led, grouped with near-identical texts.
+#[derive(Debug)]
+pub struct Group {
+    /// Text of the first occurrence.
+    pub text: String,
+    pub spdx: String,
+    pub files: usize,
+    words: HashSet<String>,
+}
+
+/// Distinct headers in first-seen order.
+#[derive(Debug, Default)]
+pub struct Catalog {
+    groups: Vec<Group>,
+    limit: Option<usize>,
+}
+
+impl Catalog {
+    /// Counts `text` in the group of a similar header, or starts a new group.
+    /// Texts that fit no group are dropped once `limit` groups exist.
+    pub fn add(&mut self, text: &str) {
+        let text_words = words(text);
+        if let Some(group) = self
+            .groups
+            .iter_mut()
+            .find(|g| similar(&g.words, &text_words))
+        {
+            group.files += 1;
+            return;
+        }
+        if self.limit.is_some_and(|limit| self.groups.len() >= limit) {
+            return;
+        }
+        self.groups.push(Group {
+            text: text.to_string(),
+            spdx: spdx_id(text),
+            files: 1,
+            words: text_words,
+        });
+    }
+
+    pub fn groups(&self) -> &[Group] {
+        &self.groups
+    }
+}
+
+/// Leading comment blocks without a license marker that repeat across files.
+/// Built by a scan of all file heads before processing, so that every file is
+/// judged against the same set regardless of processing order.
+#[derive(Debug, Default)]
+pub struct Headers {
+    repeated: Vec<HashSet<String>>,
+}
+
+impl Headers {
+    /// `blocks` holds the unmarked leading blocks of each file, as returned by `unmarked_blocks`.
+    pub fn scan(blocks: impl IntoIterator<Item = Vec<String>>) -> Self {
+        let mut catalog = Catalog {
+            groups: Vec::new(),
+            limit: Some(SCAN_GROUPS),
+        };
+        for file_blocks in blocks {
+            for block in file_blocks {
+                catalog.add(&block);
+            }
+        }
+        Self {
+            repeated: catalog
+                .groups
+                .into_iter()
+                .filter(|g| g.files >= REPEAT_MIN)
+                .map(|g| g.words)
+                .collect(),
+        }
+    }
+
+    fn is_header(&self, text: &str) -> bool {
+        if has_marker(text) {
+            return true;
+        }
+        let text_words = words(text);
+        text_words.len() >= MIN_WORDS && self.repeated.iter().any(|r| similar(r, &text_words))
+    }
+}
+
+/// Leading comment blocks of a file that lack a license marker, as input for `Headers::scan`.
+pub fn unmarked_blocks(content: &str, syntax: &Syntax) -> Vec<String> {
+    let found = comments::find_comments(content, syntax);
+    comments::leading_blocks(content, found, syntax)
+        .iter()
+        .map(|block| comments::block_text(content, block))
+        .filter(|text| !has_marker(text) && words(text).len() >= MIN_WORDS)
+        .collect()
+}
+
+/// Removes the license header at the top of `content`, if there is one.
+/// Returns the new content, the indices of the lines that were kept and the removed header text.
+pub fn remove(
+    content: &str,
+    syntax: &Syntax,
+    headers: &Headers,
+) -> (String, Vec<usize>, Option<String>) {
+    let found = comments::find_comments(content, syntax);
+    let header: Vec<_> = comments::leading_blocks(content, found, syntax)
+        .into_iter()
+        .take_while(|block| headers.is_header(&comments::block_text(content, block)))
+        .collect();
+    if header.is_empty() {
+        return (
+            content.to_string(),
+            (0..lines::count(content)).collect(),
+            None,
+        );
+    }
+
+    let text = header
+        .iter()
+        .map(|block| comments::block_text(content, block))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let (stripped, kept) = comments::remove(content, header.into_iter().flatten().collect());
+
+    // Drop the blank lines that separated the header from the code
+    let mut out = String::with_capacity(stripped.len());
+    let mut kept_after = Vec::with_capacity(kept.len());
+    let mut leading = true;
+    for (idx, line) in stripped.split_inclusive('\n').enumerate() {
+        let shebang = idx == 0 && line.starts_with("#!");
+        if leading && !shebang && line.trim().is_empty() {
+            continue;
+        }
+        leading = shebang;
+        out.push_str(line);
+        kept_after.push(kept[idx]);
+    }
+    (out, kept_after, Some(text))
+}
+
+/// Inserts a one-line `SPDX: <id>` comment where the header was, after any `#!` line.
+/// Returns the new content and the index of the inserted line.
+pub fn insert_marker(content: &str, syntax: &Syntax, header: &str) -> (String, usize) {
+    let id = spdx_id(header);
+    let marker = match (syntax.line.first(), syntax.block) {
+        (Some(line), _) => format!("{} SPDX: {}\n", line, id),
+        (None, Some((open, close))) => format!("{} SPDX: {} {}\n", open, id, close),
+        (None, None) => return (content.to_string(), 0),
+    };
+    let at = shebang_end(content);
+    let mut out = String::with_capacity(content.len() + marker.len() + 1);
+    out.push_str(&content[..at]);
+    if at == content.len() && at > 0 && !content.ends_with('\n') {
+        out.push('\n');
+    }
+    out.push_str(&marker);
+    out.push_str(&content[at..]);
+    (out, usize::from(at > 0))
+}
+
+/// The SPDX identifier named in a header or recognized from its text, else `NOASSERTION`.
+pub fn spdx_id(header: &str) -> String {
+    // ASCII lowercasing keeps byte offsets, so `at` is valid in `header` too
+    if let Some(at) = header.to_ascii_lowercase().find("spdx-license-identifier:") {
+        let rest = &header[at + "spdx-license-identifier:".len()..];
+        let id = rest
+            .lines()
+            .next()
+            .unwrap_or("")
+            .trim()
+            .trim_end_matches("*/")
+            .trim_end_matches("-->")
+            .trim_end_matches("-}")
+            .trim();
+        if !id.is_empty() {
+            return id.to_string();
+        }
+    }
+    // Line breaks and comment markers split the phrases, so match on the words alone
+    let flat = header
+        .to_lowercase()
+        .split(|c: char| c.is_whitespace() || matches!(c, '/' | '*' | '#' | '-' | '!'))
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    KNOWN_LICENSES
+        .iter()
+        .find(|(phrases, _)| phrases.iter().all(|p| flat.contains(p)))
+        .map_or("NOASSERTION", |(_, id)| id)
+        .to_string()
+}
+
+/// Offset just past a leading `#!` line, or 0.
+fn shebang_end(content: &str) -> usize {
+    if content.starts_with("#!") {
+        content.find('\n').map_or(content.len(), |i| i + 1)
+    } else {
+        0
+    }
+}
@@ -0,0 +1,53 @@
+/// Number of lines in `text`, each ending after its `\n` (a final line may lack one).
+/// Transforms report the lines they keep as indices in this numbering.
+pub fn count(text: &str) -> usize {
+    text.split_inclusive('\n').count()
+}
+
+/// Original line numbers of transformed content, for `--line-numbers`.
+#[derive(Debug, Clone)]
+pub struct LineMap {
+    /// 1-based line in the original file for each current line; `None` for added lines.
+    origins: Vec<Option<usize>>,
+}
+
+impl LineMap {
+    pub fn new(content: &str) -> Self {
+        Self {
+            origins: (1..=count(content)).map(Some).collect(),
+        }
+    }
+
+    /// Applies a transform that kept only the lines at `kept`, in order.
+    pub fn retain(&mut self, kept: &[usize]) {
+        self.origins = kept
+            .iter()
+            .map(|&idx| self.origins.get(idx).copied().flatten())
+            .collect();
+    }
+
+    /// Records a line added at index `at` that has no original line.
+    pub fn insert(&mut self, at: usize) {
+        self.origins.insert(at.min(self.origins.len()), None);
+    }
+
+    /// Prefixes each line with its right-aligned original line number.
+    pub fn number(&self, content: &str) -> String {
+        let max = self.origins.iter().flatten().max().copied().unwrap_or(0);
+        let width = max.to_string().len();
+        let mut out = String::with_capacity(content.len() + count(content) * (width + 3));
+        for (idx, line) in content.split_inclusive('\n').enumerate() {
+            let gutter = match self.origins.get(idx).copied().flatten() {
+                Some(n) => format!("{:>width$} |", n, width = width),
+                None => format!("{:>width$} |", "", width = width),
+            };
+            out.push_str(&gutter);
+            // No trailing space on blank lines
+            if !line.trim_end_matches(['\n', '\r']).is_empty() {
+                out.push(' ');
+            }
+            out.push_str(line);
+        }
+        out
+    }
+}
@@ -1,15 +1,60 @@
 mod args;
+mod comments;
+mod compact;
 mod config;
 mod discovery;
+mod git;
+mod imports;
+mod language;
+mod license;
+mod lines;
 mod output;
+mod parallel;
 mod processing;
+mod relevance;
+mod secrets;
+mod toc;
+mod tokens;
 
 use anyhow::{Context, Result};
 use args::Args;
 use clap::Parser;
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
+/// Number of commit subjects listed by `--git-info`.
+const RECENT_COMMITS: usize = 5;
+
+// Files are processed in parallel windows and committed in discovery order.
+// A window closes at this many files or bytes, which bounds the memory held.
+const WINDOW_FILES: usize = 1024;
+const WINDOW_BYTES: u64 = 64 * 1024 * 1024;
+
+/// A discovered file after the parallel stage, before the budgets are applied in order.
+enum Pending {
+    MetadataError(PathBuf, String),
+    /// Cannot fit even in the budget left at the start of the window; never read.
+    OverBudget(PathBuf, u64),
+    Processed(u64, processing::FileStatus),
+}
+
+/// A file committed in rank order, written once every file is committed, in path order.
+enum Selected {
+    /// Processed again when written, so no content is held until then.
+    Included(PathBuf, u64),
+    Omitted(processing::FileStatus),
+}
+
+impl Selected {
+    fn path(&self) -> &Path {
+        match self {
+            Selected::Included(path, _) => path,
+            Selected::Omitted(status) => status.path(),
+        }
+    }
+}
+
 /// Strips Windows extended-length path prefix and normalizes to forward slashes.
 pub fn clean_path(p: &Path) -> String {
     p.to_string_lossy()
@@ -17,6 +62,29 @@ pub fn clean_path(p: &Path) -> String {
         .replace('\\', "/")
 }
 
+/// Position of a `--seed` among the discovered files. Relative seeds are taken from the
+/// root, or from the working directory when no such file exists under the root.
+fn seed_index(root: &Path, seed: &Path, files: &[PathBuf]) -> Result<usize> {
+    let candidates = [root.join(seed), seed.to_path_buf()];
+    let rel = candidates
+        .iter()
+        .find_map(|path| {
+            let path = std::fs::canonicalize(path).ok()?;
+            path.strip_prefix(root).ok().map(clean_path)
+        })
+        // Under --rev the seed may only exist in the revision
+        .unwrap_or_else(|| clean_path(seed).trim_start_matches("./").to_string());
+    files
+        .iter()
+        .position(|path| clean_path(path.strip_prefix(root).unwrap_or(path)) == rel)
+        .with_context(|| {
+            format!(
+                "Seed is not among the discovered files (missing or excluded): {}",
+                clean_path(seed)
+            )
+        })
+}
+
 fn main() -> Result<()> {
     let start_time = Instant::now();
     let args = Args::parse();
@@ -27,38 +95,38 @@ fn main() -> Result<()> {
         args.root.display()
     ))?;
 
-    // Load global and local config
-    let mut config = config::AppConfig::load_global()?;
-
-    if let Some(local) = config::AppConfig::load_local(&root)? {
-        config = local;
+    if let Some(args::Command::Config {
+        action: args::ConfigAction::Init { global, force },
+    }) = &args.command
+    {
+        let path = if *global {
+            config::global_config_path(args.config.as_deref())
+                .map(|(path, _)| path)
+                .context("Cannot determine the global config location; set CTXSNAP_CONFIG")?
+        } else {
+            root.join(config::LOCAL_CONFIG)
+        };
+        config::AppConfig::default().save(&path, *force, !*global)?;
+        eprintln!("Initialized config: {}", clean_path(&path));
+        return Ok(());
     }
 
-    if let Some(v) = args.max_file_mb {
-        config.max_file_mb = v;
-    }
-    if let Some(v) = args.max_total_mb {
-        config.max_total_mb = v;
-    }
-    if let Some(v) = args.depth {
-        config.depth = v;
-    }
-    if args.remove_comments {
-        config.remove_comments = true;
-    }
-    if args.include_lockfiles {
-        config.include_lockfiles = true;
-    }
-    if args.no_gitignore {
-        config.use_gitignore = false;
+    // Merge defaults, global and local config, the selected profile and CLI flags
+    let (config, sources) = config::AppConfig::load(
+        &root,
+        args.config.as_deref(),
+        args.profile.as_deref(),
+        args.config_layer(),
+    )?;
+
+    if args.show_config {
+        print!("{}", config.describe(&sources)?);
+        return Ok(());
     }
-    config.exclude_ext.extend(args.exclude_ext.clone());
-    config.exclude_dir.extend(args.exclude_dir.clone());
-    config.exclude_file.extend(args.exclude_file.clone());
 
     // Handle --init
     if args.init {
-        config.save_local(&root)?;
+        config.save(&root.join(config::LOCAL_CONFIG), true, true)?;
         eprintln!(
             "Initialized local config: {}/ctxsnap.toml",
             clean_path(&root)
@@ -76,47 +144,306 @@ fn main() -> Result<()> {
 
     eprintln!("Scanning: {}", clean_path(&root));
 
+    // Git change selection
+    let changes = args
+        .change_scope()
+        .map(|scope| git::ChangeSet::load(&root, scope))
+        .transpose()?;
+    if let Some(changes) = &changes {
+        eprintln!("Changes:  {} files {}", changes.count(), changes.describe());
+    }
+
+    // Committed tree read from the object database
+    let revision = args
+        .rev
+        .as_deref()
+        .map(|rev| git::Revision::load(&root, rev))
+        .transpose()?;
+    if let Some(revision) = &revision {
+        eprintln!("Revision: {} ({})", revision.name, revision.commit);
+    }
+
     // Discovery
-    let discovery = discovery::find_files(&root, &config)?;
+    let mut discovery = match &revision {
+        Some(revision) => discovery::find_files_in_revision(&root, &config, revision)?,
+        None => discovery::find_files(&root, &config, changes.as_ref())?,
+    };
     let total_found = discovery.files.len();
 
     eprintln!("Found:    {} files", total_found);
 
-    // Processing
-    let max_total_bytes = config.max_total_mb.saturating_mul(1024 * 1024);
+    let jobs = args.jobs.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    });
+
+    // Import-graph expansion: only files reachable from the seeds, nearest first
+    if !args.seed.is_empty() {
+        let seeds = args
+            .seed
+            .iter()
+            .map(|seed| seed_index(&root, seed, &discovery.files))
+            .collect::<Result<Vec<_>>>()?;
+        discovery.files = imports::reachable(
+            std::mem::take(&mut discovery.files),
+            &root,
+            &seeds,
+            args.seed_depth,
+            jobs,
+            |path| processing::read_text(path, &root, revision.as_ref(), &config),
+        );
+        eprintln!(
+            "Seeds:    {} files reachable from {} seeds",
+            discovery.files.len(),
+            seeds.len()
+        );
+    }
+
+    // Relevance ranking: the budgets are filled with the highest-scoring files first
+    let mut scores = HashMap::new();
+    // Discovery position of each file, to write a ranked snapshot in path order
+    let mut path_order: Option<HashMap<PathBuf, usize>> = None;
+    if let Some(text) = &args.query {
+        let query = relevance::Query::new(text);
+        let stats = parallel::map_ordered(discovery.files.clone(), jobs, |path| {
+            processing::query_stats(&path, &root, revision.as_ref(), &config, &query)
+        });
+        let ranked = relevance::scores(&stats);
+        if config.query_order == relevance::QueryOrder::Path {
+            path_order = Some(
+                discovery
+                    .files
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, path)| (path.clone(), idx))
+                    .collect(),
+            );
+        }
+        let mut files: Vec<_> = discovery.files.drain(..).zip(ranked).collect();
+        // Stable, so equal scores keep discovery order
+        files.sort_by(|a, b| b.1.total_cmp(&a.1));
+        for (path, score) in files {
+            scores.insert(clean_path(path.strip_prefix(&root).unwrap_or(&path)), score);
+            discovery.files.push(path);
+        }
+        eprintln!("Query:    {} files ranked", scores.len());
+    }
+
+    let mut shared = processing::Shared::default();
+    if config.scan_secrets || config.fail_on_secrets {
+        shared.secrets = secrets::Scanner::new(&config.secret_patterns)?;
+    }
+    // Leading blocks repeated across files are headers even without a license marker,
+    // so every file head is scanned before any file is processed
+    if config.license_headers != license::LicenseMode::Keep {
+        let blocks = parallel::map_ordered(discovery.files.clone(), jobs, |path| {
+            processing::license_candidates(&path, &root, revision.as_ref())
+        });
+        shared.headers = license::Headers::scan(blocks);
+    }
+    // Files with redacted secrets, reported instead of writing under --fail-on-secrets
+    let mut secret_files = Vec::new();
+
+    // Processing. A split snapshot takes every file, in as many parts as it needs,
+    // so the total budgets only apply to a single output.
+    let split = config.part_max_mb.is_some() || config.part_max_tokens.is_some();
+    let max_total_bytes = if split {
+        u64::MAX
+    } else {
+        config.max_total_mb.saturating_mul(1024 * 1024)
+    };
+    let max_total_tokens = config.max_total_tokens.filter(|_| !split);
     let mut used: u64 = 0;
+    let mut used_tokens: u64 = 0;
 
-    let mut writer = output::SnapshotWriter::new(root.clone());
-
-    for path in discovery.files {
-        let size = match std::fs::metadata(&path) {
-            Ok(m) => m.len(),
-            Err(e) => {
-                writer.process_status(processing::FileStatus::Omitted {
-                    path,
-                    reason: format!("Metadata error: {}", e),
-                    size: 0,
-                })?;
-                continue;
+    let mut writer = output::SnapshotWriter::new(root.clone(), &config, args.diff);
+
+    let rev = match &revision {
+        Some(revision) => {
+            writer.set_revision(&revision.name, &revision.commit);
+            revision.commit.clone()
+        }
+        None => "HEAD".to_string(),
+    };
+
+    // Git metadata is best effort: a missing repository should not fail the snapshot
+    if config.git_info {
+        let commit = revision.as_ref().map(|r| r.commit.as_str());
+        match git::RepoInfo::load(&root, commit, RECENT_COMMITS) {
+            Ok(info) => writer.set_repo_info(info),
+            Err(e) => eprintln!("Warning: Could not read git info: {:#}", e),
+        }
+    }
+    if config.git_file_info {
+        let rel_paths = discovery
+            .files
+            .iter()
+            .map(|p| clean_path(p.strip_prefix(&root).unwrap_or(p)))
+            .collect();
+        match git::last_commits(&root, &rev, &rel_paths) {
+            Ok(commits) => writer.set_file_commits(commits),
+            Err(e) => eprintln!("Warning: Could not read git file history: {:#}", e),
+        }
+    }
+    if !scores.is_empty() {
+        writer.set_scores(scores);
+    }
+
+    let budget_exceeded = |path, size| processing::FileStatus::Omitted {
+        path,
+        reason: format!("Budget exceeded (limit={} MB)", config.max_total_mb),
+        size,
+    };
+
+    let process = |path: PathBuf| {
+        let status = match &revision {
+            Some(revision) => processing::process_blob(path, &root, revision, &config, &shared),
+            None => processing::process_file(path, &config, &shared),
+        };
+        let status = match (&changes, args.diff) {
+            (Some(changes), Some(mode)) => {
+                processing::attach_diff(status, changes, mode, &root, &config, &shared)
             }
+            _ => status,
         };
+        processing::limit_tokens(status, &config)
+    };
+
+    // Files committed by rank, held back until they can be written in path order
+    let mut deferred = path_order.as_ref().map(|_| Vec::new());
+    let mut emit = |status: processing::FileStatus| match &mut deferred {
+        Some(deferred) => {
+            deferred.push(match status {
+                processing::FileStatus::Included { path, size, .. } => {
+                    Selected::Included(path, size)
+                }
+                status => Selected::Omitted(status),
+            });
+            Ok(())
+        }
+        None => writer.process_status(status),
+    };
 
-        if used.saturating_add(size) > max_total_bytes {
-            writer.process_status(processing::FileStatus::Omitted {
-                path,
-                reason: format!("Budget exceeded (limit={} MB)", config.max_total_mb),
-                size,
-            })?;
-            continue;
+    let mut files = discovery.files.into_iter().peekable();
+    while files.peek().is_some() {
+        let mut window = Vec::new();
+        let mut window_bytes: u64 = 0;
+        while window.len() < WINDOW_FILES && window_bytes < WINDOW_BYTES {
+            let Some(path) = files.next() else {
+                break;
+            };
+            let size = match &revision {
+                Some(revision) => {
+                    let rel = clean_path(path.strip_prefix(&root).unwrap_or(&path));
+                    Ok(revision.size(&rel).unwrap_or(0))
+                }
+                None => std::fs::metadata(&path)
+                    .map(|m| m.len())
+                    .map_err(|e| format!("Metadata error: {}", e)),
+            };
+            window_bytes = window_bytes.saturating_add(*size.as_ref().unwrap_or(&0));
+            window.push((path, size));
         }
 
-        let status = processing::process_file(path, &config);
+        // The budget only grows, so a file over it now is over it at commit time too
+        let used_before = used;
+        let pending = parallel::map_ordered(window, jobs, |(path, size)| match size {
+            Err(e) => Pending::MetadataError(path, e),
+            Ok(size) if used_before.saturating_add(size) > max_total_bytes => {
+                Pending::OverBudget(path, size)
+            }
+            Ok(size) => Pending::Processed(size, process(path)),
+        });
+
+        // Commit in discovery order with exact budget accounting
+        for item in pending {
+            let (size, status) = match item {
+                Pending::MetadataError(path, reason) => {
+                    emit(processing::FileStatus::Omitted {
+                        path,
+                        reason,
+                        size: 0,
+                    })?;
+                    continue;
+                }
+                Pending::OverBudget(path, size) => {
+                    emit(budget_exceeded(path, size))?;
+                    continue;
+                }
+                Pending::Processed(size, status) => (size, status),
+            };
+
+            if used.saturating_add(size) > max_total_bytes {
+                emit(budget_exceeded(status.into_path(), size))?;
+                continue;
+            }
 
-        if let processing::FileStatus::Included { size, .. } = &status {
-            used = used.saturating_add(*size);
+            let status = match status {
+                // Token counts are only known after processing
+                processing::FileStatus::Included {
+                    path, size, tokens, ..
+                } if max_total_tokens
+                    .is_some_and(|max| used_tokens.saturating_add(tokens) > max) =>
+                {
+                    processing::FileStatus::Omitted {
+                        path,
+                        reason: format!(
+                            "Token budget exceeded (limit={} tokens)",
+                            max_total_tokens.unwrap_or_default()
+                        ),
+                        size,
+                    }
+                }
+                status => {
+                    if let processing::FileStatus::Included { size, tokens, .. } = &status {
+                        used = used.saturating_add(*size);
+                        used_tokens = used_tokens.saturating_add(*tokens);
+                    }
+                    status
+                }
+            };
+
+            if let processing::FileStatus::Included { path, secrets, .. } = &status {
+                if config.fail_on_secrets && !secrets.is_empty() {
+                    let rel = clean_path(path.strip_prefix(&root).unwrap_or(path));
+                    secret_files.push(format!("{}: {}", rel, secrets::describe(secrets)));
+                }
+            }
+            emit(status)?;
+        }
+    }
+    if let (Some(mut deferred), Some(path_order)) = (deferred, path_order) {
+        deferred.sort_by_key(|selected| path_order.get(selected.path()).copied());
+        let mut selected = deferred.into_iter().peekable();
+        while selected.peek().is_some() {
+            let mut window = Vec::new();
+            let mut window_bytes: u64 = 0;
+            while window.len() < WINDOW_FILES && window_bytes < WINDOW_BYTES {
+                let Some(item) = selected.next() else {
+                    break;
+                };
+                if let Selected::Included(_, size) = &item {
+                    window_bytes = window_bytes.saturating_add(*size);
+                }
+                window.push(item);
+            }
+            let statuses = parallel::map_ordered(window, jobs, |item| match item {
+                Selected::Included(path, _) => process(path),
+                Selected::Omitted(status) => status,
+            });
+            for status in statuses {
+                writer.process_status(status)?;
+            }
         }
+    }
 
-        writer.process_status(status)?;
+    if !secret_files.is_empty() {
+        anyhow::bail!(
+            "Secrets found in {} files (--fail-on-secrets); no snapshot written:\n  {}",
+            secret_files.len(),
+            secret_files.join("\n  ")
+        );
     }
 
     // Finalize
@@ -130,6 +457,9 @@ fn main() -> Result<()> {
     } else {
         eprintln!("Output:   (Dry Run - Stdout)");
     }
+    if let Some(parts) = stats.parts {
+        eprintln!("Parts:    {}", parts);
+    }
 
     eprintln!(
         "Stats:    {} included, {} omitted",
@@ -140,14 +470,26 @@ fn main() -> Result<()> {
         stats.total_bytes as f64 / 1024.0 / 1024.0,
         stats.total_lines
     );
+    eprintln!(
+        "Tokens:   {} ({})",
+        stats.total_tokens,
+        config.tokenizer.name()
+    );
+
+    if !stats.secrets.is_empty() {
+        eprintln!("Secrets:  {}", secrets::describe(&stats.secrets));
+    }
 
-    if !stats.stats_by_extension.is_empty() {
-        eprintln!("\nComposition by Type:");
-        let mut breakdown: Vec<_> = stats.stats_by_extension.iter().collect();
-        breakdown.sort_by(|a, b| b.1 .1.cmp(&a.1 .1));
-        for (ext, (count, size)) in breakdown {
-            let mb = *size as f64 / 1024.0 / 1024.0;
-            eprintln!("  .{:<8} {:>10.2} MB ({:>4} files)", ext, mb, count);
+    if !stats.stats_by_language.is_empty() {
+        eprintln!("\nComposition by Language:");
+        let mut breakdown: Vec<_> = stats.stats_by_language.iter().collect();
+        breakdown.sort_by_key(|b| std::cmp::Reverse(b.1.bytes));
+        for (language, type_stats) in breakdown {
+            let mb = type_stats.bytes as f64 / 1024.0 / 1024.0;
+            eprintln!(
+                "  {:<12} {:>10.2} MB {:>10} tokens ({:>4} files)",
+                language, mb, type_stats.tokens, type_stats.files
+            );
         }
     }
 
@@ -1,66 +1,274 @@
 use crate::args::Args;
+use crate::config::AppConfig;
+use crate::git::{CommitSummary, DiffMode, RepoInfo};
+use crate::license::Catalog;
 use crate::processing::FileStatus;
+use crate::secrets::{self, Redactions};
+use crate::toc::{TocStyle, Tree};
+use crate::tokens::Tokenizer;
 use anyhow::{Context, Result};
 use chrono::Local;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::{File, OpenOptions};
 use std::io::{self, BufWriter, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use tempfile::SpooledTempFile;
 
+/// Document format of the snapshot.
+#[derive(Serialize, Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Markdown,
+    Json,
+    /// Claude-style `<document>` tags.
+    Xml,
+}
+
+impl OutputFormat {
+    /// File extension used for the default output name.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Markdown => "md",
+            OutputFormat::Json => "json",
+            OutputFormat::Xml => "xml",
+        }
+    }
+}
+
+/// Per-extension composition counters.
+#[derive(Serialize, Debug, Default, Clone, Copy)]
+pub struct TypeStats {
+    pub files: usize,
+    pub bytes: u64,
+    pub tokens: u64,
+}
+
 pub struct SnapshotStats {
     pub output_path: Option<PathBuf>,
+    /// Number of part documents when the output was split.
+    pub parts: Option<usize>,
     pub total_files: usize,
     pub total_bytes: u64,
     pub total_lines: usize,
+    pub total_tokens: u64,
     pub omitted_count: usize,
-    pub stats_by_extension: HashMap<String, (usize, u64)>,
+    /// Secrets redacted across all files, by kind.
+    pub secrets: Redactions,
+    /// Included files by detected language, `(unknown)` when none was detected.
+    pub stats_by_language: HashMap<&'static str, TypeStats>,
     pub top_offenders: Vec<(PathBuf, u64)>, // (Path, Size)
 }
 
-pub struct SnapshotWriter {
+// JSON document pieces. Files are streamed through the spool, the rest is small.
+#[derive(Serialize)]
+struct JsonFile<'a> {
+    path: &'a str,
+    language: Option<&'a str>,
+    size: u64,
+    lines: usize,
+    tokens: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    piece: Option<Piece>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_commit: Option<&'a CommitSummary>,
+    content: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct JsonOmitted<'a> {
+    path: String,
+    size: u64,
+    reason: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonStats<'a> {
+    files_included: usize,
+    files_omitted: usize,
+    total_bytes: u64,
+    total_lines: usize,
+    total_tokens: u64,
+    tokenizer: &'a str,
+    secrets_redacted: &'a Redactions,
+    by_extension: BTreeMap<&'a str, TypeStats>,
+    by_language: BTreeMap<&'a str, TypeStats>,
+}
+
+#[derive(Serialize)]
+struct JsonLicense<'a> {
+    spdx: &'a str,
+    files: usize,
+    text: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonPart {
+    part: usize,
+    file: String,
+    bytes: u64,
+    tokens: u64,
+    files: Vec<String>,
+}
+
+/// Line range of a file too large to fit in a single part.
+#[derive(Serialize, Debug, Clone, Copy)]
+struct Piece {
+    index: usize,
+    count: usize,
+    first_line: usize,
+    last_line: usize,
+}
+
+struct TocEntry {
+    path: PathBuf,
+    /// Markdown anchor of the entry's section.
+    anchor: String,
+    /// Size of the whole file, also for pieces.
+    size: u64,
+    tokens: u64,
+    /// Relevance to the `--query`, if one was given.
+    score: Option<f64>,
+    piece: Option<Piece>,
+}
+
+/// One output document body. Without part limits everything lands in a single part.
+struct Part {
     // Stores body content (files code)
     body_writer: BufWriter<SpooledTempFile>,
-    // Track included files for TOC
-    included_paths: Vec<PathBuf>,
+    // Track included files (and their token counts) for TOC
+    entries: Vec<TocEntry>,
+    bytes: u64,
+    tokens: u64,
+}
+
+impl Part {
+    fn new() -> Self {
+        Self {
+            // Buffer up to 2MB in RAM before spilling to disk for the temp body
+            body_writer: BufWriter::new(SpooledTempFile::new(2 * 1024 * 1024)),
+            entries: Vec::new(),
+            bytes: 0,
+            tokens: 0,
+        }
+    }
+}
+
+pub struct SnapshotWriter {
+    parts: Vec<Part>,
+    // Included files with their token counts and the parts they landed in
+    included_paths: Vec<(PathBuf, u64, Vec<usize>)>,
     // Track omitted files for report
     omitted: Vec<(PathBuf, String, u64)>,
     // Stats
     total_bytes: u64,
     total_lines: usize,
-    stats_by_extension: HashMap<String, (usize, u64)>,
+    total_tokens: u64,
+    secrets: Redactions,
+    stats_by_extension: HashMap<String, TypeStats>,
+    // Keyed by canonical language name, `(unknown)` when none was detected
+    stats_by_language: HashMap<&'static str, TypeStats>,
     top_offenders: Vec<(PathBuf, u64)>,
 
     root: PathBuf,
+    format: OutputFormat,
+    tokenizer: Tokenizer,
+    part_max_bytes: Option<u64>,
+    part_max_tokens: Option<u64>,
+    toc: TocStyle,
+    // Also list omitted files in a tree TOC
+    toc_omitted: bool,
+    toc_depth: Option<usize>,
+    diff_mode: Option<DiffMode>,
+    // (ref as given, resolved commit) under `--rev`
+    revision: Option<(String, String)>,
+    repo_info: Option<RepoInfo>,
+    // Last commit per relative path
+    file_commits: HashMap<String, CommitSummary>,
+    // Relevance score per relative path under `--query`
+    scores: HashMap<String, f64>,
+    // License headers removed from files, listed once in the header
+    licenses: Catalog,
+    // Section anchors handed out so far, to keep them unique
+    anchors: HashSet<String>,
     timestamp: String,
     timestamp_file_fmt: String,
 }
 
 impl SnapshotWriter {
-    pub fn new(root: PathBuf) -> Self {
+    pub fn new(root: PathBuf, config: &AppConfig, diff_mode: Option<DiffMode>) -> Self {
         let now = Local::now();
         Self {
-            // Buffer up to 2MB in RAM before spilling to disk for the temp body
-            body_writer: BufWriter::new(SpooledTempFile::new(2 * 1024 * 1024)),
+            parts: vec![Part::new()],
             included_paths: Vec::new(),
             omitted: Vec::new(),
             total_bytes: 0,
             total_lines: 0,
+            total_tokens: 0,
+            secrets: Redactions::new(),
             stats_by_extension: HashMap::new(),
+            stats_by_language: HashMap::new(),
             top_offenders: Vec::new(),
             root,
+            format: config.format,
+            tokenizer: config.tokenizer,
+            part_max_bytes: config.part_max_mb.map(|mb| mb.saturating_mul(1024 * 1024)),
+            part_max_tokens: config.part_max_tokens,
+            toc: config.toc,
+            toc_omitted: config.toc_omitted,
+            toc_depth: config.toc_depth,
+            diff_mode,
+            revision: None,
+            repo_info: None,
+            file_commits: HashMap::new(),
+            scores: HashMap::new(),
+            licenses: Catalog::default(),
+            anchors: HashSet::new(),
             timestamp: now.format("%Y-%m-%d %H:%M:%S").to_string(),
             timestamp_file_fmt: now.format("%Y%m%d_%H%M%S").to_string(),
         }
     }
 
+    pub fn set_revision(&mut self, name: &str, commit: &str) {
+        self.revision = Some((name.to_string(), commit.to_string()));
+    }
+
+    pub fn set_repo_info(&mut self, info: RepoInfo) {
+        self.repo_info = Some(info);
+    }
+
+    pub fn set_file_commits(&mut self, commits: HashMap<String, CommitSummary>) {
+        self.file_commits = commits;
+    }
+
+    pub fn set_scores(&mut self, scores: HashMap<String, f64>) {
+        self.scores = scores;
+    }
+
     pub fn process_status(&mut self, status: FileStatus) -> Result<()> {
         match status {
             FileStatus::Included {
                 path,
                 content,
                 size,
+                tokens,
+                language,
+                diff,
+                license,
+                secrets,
             } => {
+                if let Some(text) = &license {
+                    self.licenses.add(text);
+                }
+                for (kind, count) in secrets {
+                    *self.secrets.entry(kind).or_default() += count;
+                }
+
                 // Update stats
                 let ext = path
                     .extension()
@@ -69,16 +277,69 @@ impl SnapshotWriter {
                     .to_string();
 
                 let entry = self.stats_by_extension.entry(ext).or_default();
-                entry.0 += 1; // count
-                entry.1 += size; // bytes
+                entry.files += 1;
+                entry.bytes += size;
+                entry.tokens += tokens;
+
+                let entry = self
+                    .stats_by_language
+                    .entry(language.unwrap_or("(unknown)"))
+                    .or_default();
+                entry.files += 1;
+                entry.bytes += size;
+                entry.tokens += tokens;
 
                 // Track for top offenders (sorted once at finalize)
                 self.top_offenders.push((path.clone(), size));
 
-                self.write_file_content(&path, &content)?;
-                self.included_paths.push(path);
+                let mut file_parts = Vec::new();
+                let pieces = self.split_pieces(&content, diff.as_deref(), tokens);
+                let last = pieces.len() - 1;
+                for (idx, (text, mut piece_tokens, piece)) in pieces.into_iter().enumerate() {
+                    // The diff follows the last piece of the file
+                    let diff = diff.as_deref().filter(|_| idx == last);
+                    if let (Some(d), Some(_)) = (diff, piece) {
+                        piece_tokens += self.tokenizer.count(d);
+                    }
+                    let bytes = (text.len() + diff.map_or(0, str::len)) as u64;
+                    self.reserve_part(bytes, piece_tokens)?;
+                    let anchor = self.new_anchor(&path, piece);
+                    let score = self.scores.get(&self.rel_path_str(&path)).copied();
+                    match self.format {
+                        OutputFormat::Markdown => {
+                            self.write_file_content(&path, &anchor, language, text, diff, piece)?
+                        }
+                        OutputFormat::Json => self.write_file_json(
+                            &path,
+                            language,
+                            text,
+                            diff,
+                            size,
+                            piece_tokens,
+                            score,
+                            piece,
+                        )?,
+                        OutputFormat::Xml => self.write_file_xml(&path, text, diff, piece)?,
+                    }
+
+                    let part = self.current_part();
+                    part.entries.push(TocEntry {
+                        path: path.clone(),
+                        anchor,
+                        size,
+                        tokens: piece_tokens,
+                        score,
+                        piece,
+                    });
+                    part.bytes += bytes;
+                    part.tokens += piece_tokens;
+                    file_parts.push(self.parts.len());
+                }
+
+                self.included_paths.push((path, tokens, file_parts));
                 self.total_bytes += size;
                 self.total_lines += content.lines().count();
+                self.total_tokens += tokens;
             }
             FileStatus::Omitted { path, reason, size } => {
                 self.omitted.push((path, reason, size));
@@ -87,84 +348,639 @@ impl SnapshotWriter {
         Ok(())
     }
 
-    fn write_file_content(&mut self, path: &Path, content: &str) -> Result<()> {
+    fn is_split(&self) -> bool {
+        self.part_max_bytes.is_some() || self.part_max_tokens.is_some()
+    }
+
+    fn current_part(&mut self) -> &mut Part {
+        self.parts
+            .last_mut()
+            .expect("writer always has at least one part")
+    }
+
+    fn fits_part(&self, part_bytes: u64, part_tokens: u64, bytes: u64, tokens: u64) -> bool {
+        self.part_max_bytes
+            .map_or(true, |max| part_bytes.saturating_add(bytes) <= max)
+            && self
+                .part_max_tokens
+                .map_or(true, |max| part_tokens.saturating_add(tokens) <= max)
+    }
+
+    /// Starts a new part if the content would not fit in the current one.
+    fn reserve_part(&mut self, bytes: u64, tokens: u64) -> Result<()> {
+        let (part_bytes, part_tokens, empty) = {
+            let part = self.current_part();
+            (part.bytes, part.tokens, part.entries.is_empty())
+        };
+        if empty || self.fits_part(part_bytes, part_tokens, bytes, tokens) {
+            return Ok(());
+        }
+        // Finished parts go to disk so that only the open part occupies RAM
+        let body_writer = &mut self.current_part().body_writer;
+        body_writer.flush()?;
+        body_writer.get_mut().roll()?;
+        self.parts.push(Part::new());
+        Ok(())
+    }
+
+    /// Splits content larger than a whole part on line boundaries.
+    /// Content that fits is returned as a single piece.
+    fn split_pieces<'a>(
+        &self,
+        content: &'a str,
+        diff: Option<&str>,
+        tokens: u64,
+    ) -> Vec<(&'a str, u64, Option<Piece>)> {
+        let diff_bytes = diff.map_or(0, str::len) as u64;
+        if !self.is_split() || self.fits_part(0, 0, content.len() as u64 + diff_bytes, tokens) {
+            return vec![(content, tokens, None)];
+        }
+        let count_tokens = |text: &str| {
+            if self.part_max_tokens.is_some() {
+                self.tokenizer.count(text)
+            } else {
+                0
+            }
+        };
+
+        // (start byte, end byte, first line, last line)
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        let mut end = 0;
+        let mut first_line = 1;
+        let mut range_tokens = 0;
+        // (start byte, tokens) of each line in the open range
+        let mut range_lines = Vec::new();
+
+        for (idx, line) in content.split_inclusive('\n').enumerate() {
+            let line_tokens = count_tokens(line);
+            if end > start
+                && !self.fits_part(
+                    (end - start) as u64,
+                    range_tokens,
+                    line.len() as u64,
+                    line_tokens,
+                )
+            {
+                ranges.push((start, end, first_line, idx));
+                start = end;
+                first_line = idx + 1;
+                range_tokens = 0;
+                range_lines.clear();
+            }
+            range_lines.push((end, line_tokens));
+            end += line.len();
+            range_tokens += line_tokens;
+        }
+        let line_count = content.split_inclusive('\n').count();
+
+        // The diff is appended to the last piece, so that piece keeps only the lines
+        // that fit beside it (at least one)
+        if let Some(diff) = diff {
+            let diff_tokens = count_tokens(diff);
+            let mut split = range_lines.len().saturating_sub(1);
+            let mut tail_tokens = range_lines.last().map_or(0, |&(_, t)| t);
+            while split > 0 {
+                let (line_start, line_tokens) = range_lines[split - 1];
+                let fits = self.fits_part(
+                    (end - line_start) as u64,
+                    tail_tokens + line_tokens,
+                    diff_bytes,
+                    diff_tokens,
+                );
+                if !fits {
+                    break;
+                }
+                split -= 1;
+                tail_tokens += line_tokens;
+            }
+            if split > 0 {
+                let at = range_lines[split].0;
+                ranges.push((start, at, first_line, first_line + split - 1));
+                start = at;
+                first_line += split;
+            }
+        }
+        ranges.push((start, end, first_line, line_count));
+
+        let count = ranges.len();
+        ranges
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (start, end, first_line, last_line))| {
+                let text = &content[start..end];
+                let piece = Piece {
+                    index: idx + 1,
+                    count,
+                    first_line,
+                    last_line,
+                };
+                (text, self.tokenizer.count(text), Some(piece))
+            })
+            .collect()
+    }
+
+    fn rel_path_str(&self, path: &Path) -> String {
         let rel_path = path.strip_prefix(&self.root).unwrap_or(path);
-        let rel_path_str = rel_path.to_string_lossy().replace('\\', "/");
-        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        rel_path.to_string_lossy().replace('\\', "/")
+    }
+
+    /// Display label of a TOC entry: the relative path, plus the line range for pieces.
+    fn entry_label(&self, path: &Path, piece: Option<Piece>) -> String {
+        match piece {
+            Some(p) => format!(
+                "{} (lines {}-{}, piece {} of {})",
+                self.rel_path_str(path),
+                p.first_line,
+                p.last_line,
+                p.index,
+                p.count
+            ),
+            None => self.rel_path_str(path),
+        }
+    }
 
-        writeln!(self.body_writer, "## {}\n", rel_path_str)?;
+    /// Anchor of an entry's section: `file-` and the lowercased label with every run of
+    /// other characters than ASCII letters and digits replaced by `-`. Pieces add
+    /// `-piece-<n>`. An anchor already in use gets `-2`, `-3`, ... in discovery order.
+    fn new_anchor(&mut self, path: &Path, piece: Option<Piece>) -> String {
+        let mut base = format!("file-{}", slug(&self.rel_path_str(path)));
+        if let Some(p) = piece {
+            base.push_str(&format!("-piece-{}", p.index));
+        }
+        let mut anchor = base.clone();
+        let mut n = 1;
+        while !self.anchors.insert(anchor.clone()) {
+            n += 1;
+            anchor = format!("{}-{}", base, n);
+        }
+        anchor
+    }
+
+    /// Fence/language tag of an entry. Content is a unified diff under `--diff only`.
+    fn language(&self, detected: Option<&'static str>) -> Option<&'static str> {
+        if self.diff_mode == Some(DiffMode::Only) {
+            Some("diff")
+        } else {
+            detected
+        }
+    }
+
+    fn write_file_content(
+        &mut self,
+        path: &Path,
+        anchor: &str,
+        language: Option<&'static str>,
+        content: &str,
+        diff: Option<&str>,
+        piece: Option<Piece>,
+    ) -> Result<()> {
+        let label = self.entry_label(path, piece);
+        let language = self.language(language).unwrap_or("");
+        let last_commit = self.file_commits.get(&self.rel_path_str(path)).cloned();
+        let body_writer = &mut self.current_part().body_writer;
+
+        writeln!(body_writer, "<a id=\"{}\"></a>", anchor)?;
+        writeln!(body_writer, "## {}\n", label)?;
+        if let Some(c) = last_commit {
+            writeln!(
+                body_writer,
+                "**Last commit:** `{}` ({}) {}\n",
+                c.commit, c.date, c.subject
+            )?;
+        }
         let fence = crate::processing::fence_for(content);
-        writeln!(self.body_writer, "{}{}", fence, ext)?;
+        writeln!(body_writer, "{}{}", fence, language)?;
 
         // Write content and ensure it ends with a newline
-        write!(self.body_writer, "{}", content)?;
+        write!(body_writer, "{}", content)?;
         if !content.ends_with('\n') {
-            writeln!(self.body_writer)?;
+            writeln!(body_writer)?;
+        }
+
+        writeln!(body_writer, "{}\n", fence)?;
+
+        if let Some(diff) = diff {
+            let fence = crate::processing::fence_for(diff);
+            writeln!(body_writer, "{}diff", fence)?;
+            write!(body_writer, "{}", diff)?;
+            if !diff.ends_with('\n') {
+                writeln!(body_writer)?;
+            }
+            writeln!(body_writer, "{}\n", fence)?;
         }
 
-        writeln!(self.body_writer, "{}\n", fence)?;
+        writeln!(body_writer, "[Back to top](#{})\n", TOC_ANCHOR)?;
 
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
+    fn write_file_json(
+        &mut self,
+        path: &Path,
+        language: Option<&'static str>,
+        content: &str,
+        diff: Option<&str>,
+        size: u64,
+        tokens: u64,
+        score: Option<f64>,
+        piece: Option<Piece>,
+    ) -> Result<()> {
+        let rel_path_str = self.rel_path_str(path);
+        let last_commit = self.file_commits.get(&rel_path_str).cloned();
+        let entry = JsonFile {
+            path: &rel_path_str,
+            language: self.language(language),
+            last_commit: last_commit.as_ref(),
+            size,
+            lines: content.lines().count(),
+            tokens,
+            score: score.map(round_score),
+            piece,
+            content,
+            diff,
+        };
+
+        // One object per line; the array brackets are written at finalize
+        let part = self.current_part();
+        if !part.entries.is_empty() {
+            writeln!(part.body_writer, ",")?;
+        }
+        serde_json::to_writer(&mut part.body_writer, &entry)?;
+
+        Ok(())
+    }
+
+    fn write_file_xml(
+        &mut self,
+        path: &Path,
+        content: &str,
+        diff: Option<&str>,
+        piece: Option<Piece>,
+    ) -> Result<()> {
+        let rel_path_str = self.rel_path_str(path);
+        let diff_only = self.diff_mode == Some(DiffMode::Only);
+        let last_commit = self.file_commits.get(&rel_path_str).cloned();
+        let part = self.current_part();
+
+        match piece {
+            Some(p) => writeln!(
+                part.body_writer,
+                "<document index=\"{}\" lines=\"{}-{}\" piece=\"{} of {}\">",
+                part.entries.len() + 1,
+                p.first_line,
+                p.last_line,
+                p.index,
+                p.count
+            )?,
+            None => writeln!(
+                part.body_writer,
+                "<document index=\"{}\">",
+                part.entries.len() + 1
+            )?,
+        }
+        writeln!(
+            part.body_writer,
+            "<source>{}</source>",
+            xml_escape(&rel_path_str)
+        )?;
+        if let Some(c) = last_commit {
+            writeln!(
+                part.body_writer,
+                "<last_commit commit=\"{}\" date=\"{}\">{}</last_commit>",
+                xml_escape(&c.commit),
+                xml_escape(&c.date),
+                xml_escape(&c.subject)
+            )?;
+        }
+        if diff_only {
+            writeln!(part.body_writer, "<document_content format=\"diff\">")?;
+        } else {
+            writeln!(part.body_writer, "<document_content>")?;
+        }
+        write_cdata(&mut part.body_writer, content)?;
+        writeln!(part.body_writer, "</document_content>")?;
+        if let Some(diff) = diff {
+            writeln!(part.body_writer, "<document_diff>")?;
+            write_cdata(&mut part.body_writer, diff)?;
+            writeln!(part.body_writer, "</document_diff>")?;
+        }
+        writeln!(part.body_writer, "</document>")?;
+
+        Ok(())
+    }
+
+    /// Copies the spooled body of a part into the final output.
+    fn copy_body(&mut self, part: usize, final_writer: &mut dyn Write) -> Result<()> {
+        let body_writer = &mut self.parts[part].body_writer;
+        body_writer.flush()?;
+        let temp_file = body_writer.get_mut();
+        temp_file.seek(SeekFrom::Start(0))?;
+        io::copy(temp_file, final_writer)?;
+        Ok(())
+    }
+
     pub fn finalize(mut self, args: &Args, discovery_errors: &[String]) -> Result<SnapshotStats> {
         // Sort top offenders
-        self.top_offenders.sort_by(|a, b| b.1.cmp(&a.1));
+        self.top_offenders.sort_by_key(|b| std::cmp::Reverse(b.1));
         self.top_offenders.truncate(5);
 
-        let (mut final_writer, output_path): (Box<dyn Write>, Option<PathBuf>) = if args.dry_run {
-            (Box::new(BufWriter::new(io::stdout())), None)
+        let base_path = args.output.clone().unwrap_or_else(|| {
+            self.root.join(format!(
+                "merged_{}.{}",
+                self.timestamp_file_fmt,
+                self.format.extension()
+            ))
+        });
+
+        let (output_path, parts) = if !self.is_split() {
+            let mut final_writer = open_output(args, &base_path)?;
+            match self.format {
+                OutputFormat::Markdown => {
+                    self.write_markdown(&mut final_writer, discovery_errors)?
+                }
+                OutputFormat::Json => self.write_json(&mut final_writer, discovery_errors)?,
+                OutputFormat::Xml => self.write_xml(&mut final_writer, discovery_errors)?,
+            }
+            final_writer.flush()?;
+            (base_path, None)
         } else {
-            let output_path = args.output.clone().unwrap_or_else(|| {
-                self.root
-                    .join(format!("merged_{}.md", self.timestamp_file_fmt))
-            });
+            let part_names: Vec<String> = (1..=self.parts.len())
+                .map(|n| file_name(&suffixed_path(&base_path, &format!("part{}", n))))
+                .collect();
 
-            let file = if args.force {
-                File::create(&output_path).context("Failed to create output file")?
-            } else {
-                OpenOptions::new()
-                    .write(true)
-                    .create_new(true)
-                    .open(&output_path)
-                    .context(format!(
-                        "Output file exists: {:?}. Use --force.",
-                        output_path
-                    ))?
-            };
+            for (part, name) in part_names.iter().enumerate() {
+                let path = base_path.with_file_name(name);
+                let mut final_writer = open_output(args, &path)?;
+                match self.format {
+                    OutputFormat::Markdown => self.write_markdown_part(&mut final_writer, part)?,
+                    OutputFormat::Json => self.write_json_part(&mut final_writer, part)?,
+                    OutputFormat::Xml => self.write_xml_part(&mut final_writer, part)?,
+                }
+                final_writer.flush()?;
+            }
 
-            (
-                Box::new(BufWriter::with_capacity(64 * 1024, file)),
-                Some(output_path),
-            )
+            let index_path = suffixed_path(&base_path, "index");
+            let mut final_writer = open_output(args, &index_path)?;
+            match self.format {
+                OutputFormat::Markdown => {
+                    self.write_markdown_index(&mut final_writer, &part_names, discovery_errors)?
+                }
+                OutputFormat::Json => {
+                    self.write_json_index(&mut final_writer, &part_names, discovery_errors)?
+                }
+                OutputFormat::Xml => {
+                    self.write_xml_index(&mut final_writer, &part_names, discovery_errors)?
+                }
+            }
+            final_writer.flush()?;
+            (index_path, Some(self.parts.len()))
         };
 
-        let display_root = self
-            .root
+        Ok(SnapshotStats {
+            output_path: (!args.dry_run).then_some(output_path),
+            parts,
+            total_files: self.included_paths.len(),
+            total_bytes: self.total_bytes,
+            total_lines: self.total_lines,
+            total_tokens: self.total_tokens,
+            omitted_count: self.omitted.len(),
+            secrets: self.secrets,
+            stats_by_language: self.stats_by_language,
+            top_offenders: self.top_offenders,
+        })
+    }
+
+    fn display_root(&self) -> String {
+        self.root
             .to_string_lossy()
             .trim_start_matches(r"\\?\")
-            .replace('\\', "/");
+            .replace('\\', "/")
+    }
 
+    fn write_markdown(
+        &mut self,
+        final_writer: &mut dyn Write,
+        discovery_errors: &[String],
+    ) -> Result<()> {
         writeln!(final_writer, "# Project Snapshot\n")?;
-        writeln!(final_writer, "**Base path:** `{}`", display_root)?;
+        writeln!(final_writer, "**Base path:** `{}`", self.display_root())?;
+        if let Some((name, commit)) = &self.revision {
+            writeln!(final_writer, "**Revision:** `{}` (`{}`)", name, commit)?;
+        }
         writeln!(final_writer, "**Timestamp:** {}\n", self.timestamp)?;
+        self.write_markdown_git(final_writer)?;
+        self.write_markdown_licenses(final_writer)?;
+
+        self.write_markdown_toc(final_writer, 0)?;
+        self.copy_body(0, final_writer)?;
+        self.write_markdown_report(final_writer, discovery_errors)?;
+
+        Ok(())
+    }
+
+    fn write_markdown_part(&mut self, final_writer: &mut dyn Write, part: usize) -> Result<()> {
+        let count = self.parts.len();
+        writeln!(
+            final_writer,
+            "# Project Snapshot (Part {} of {})\n",
+            part + 1,
+            count
+        )?;
+        writeln!(final_writer, "**Base path:** `{}`", self.display_root())?;
+        if let Some((name, commit)) = &self.revision {
+            writeln!(final_writer, "**Revision:** `{}` (`{}`)", name, commit)?;
+        }
+        writeln!(final_writer, "**Timestamp:** {}", self.timestamp)?;
+        writeln!(final_writer, "**Part:** {} of {}\n", part + 1, count)?;
+        self.write_markdown_git(final_writer)?;
+        self.write_markdown_licenses(final_writer)?;
+
+        self.write_markdown_toc(final_writer, part)?;
+        self.copy_body(part, final_writer)?;
+
+        Ok(())
+    }
+
+    fn write_markdown_index(
+        &mut self,
+        final_writer: &mut dyn Write,
+        part_names: &[String],
+        discovery_errors: &[String],
+    ) -> Result<()> {
+        writeln!(final_writer, "# Project Snapshot Index\n")?;
+        writeln!(final_writer, "**Base path:** `{}`", self.display_root())?;
+        if let Some((name, commit)) = &self.revision {
+            writeln!(final_writer, "**Revision:** `{}` (`{}`)", name, commit)?;
+        }
+        writeln!(final_writer, "**Timestamp:** {}", self.timestamp)?;
+        writeln!(final_writer, "**Parts:** {}\n", self.parts.len())?;
+        self.write_markdown_git(final_writer)?;
+        self.write_markdown_licenses(final_writer)?;
+
+        writeln!(final_writer, "## Parts\n")?;
+        writeln!(final_writer, "| Part | File | Files | Size (MB) | Tokens |")?;
+        writeln!(final_writer, "|---:|---|---:|---:|---:|")?;
+        for (idx, part) in self.parts.iter().enumerate() {
+            writeln!(
+                final_writer,
+                "| {} | {} | {} | {:.2} | {} |",
+                idx + 1,
+                part_names[idx].replace('|', "\\|"),
+                part.entries.len(),
+                part.bytes as f64 / 1024.0 / 1024.0,
+                part.tokens
+            )?;
+        }
+        writeln!(final_writer)?;
+
+        writeln!(final_writer, "## Files\n")?;
+        writeln!(final_writer, "| Path | Part |")?;
+        writeln!(final_writer, "|---|---|")?;
+        for (path, _, file_parts) in &self.included_paths {
+            // Each part number links to the file's section in that part
+            let parts: Vec<String> = file_parts
+                .iter()
+                .map(|&p| {
+                    let anchor = self.parts[p - 1]
+                        .entries
+                        .iter()
+                        .find(|e| &e.path == path)
+                        .map_or("", |e| e.anchor.as_str());
+                    format!("[{}](<{}#{}>)", p, part_names[p - 1], anchor)
+                })
+                .collect();
+            writeln!(
+                final_writer,
+                "| {} | {} |",
+                self.rel_path_str(path).replace('|', "\\|"),
+                parts.join(", ")
+            )?;
+        }
+        writeln!(final_writer)?;
+
+        self.write_markdown_report(final_writer, discovery_errors)?;
+
+        Ok(())
+    }
+
+    fn write_markdown_git(&self, final_writer: &mut dyn Write) -> Result<()> {
+        let Some(info) = &self.repo_info else {
+            return Ok(());
+        };
+        writeln!(final_writer, "## Git\n")?;
+        writeln!(final_writer, "- **HEAD:** `{}`", info.head)?;
+        // Branch and dirty state describe the checkout, which `--rev` does not snapshot
+        if let Some(dirty) = info.dirty {
+            match &info.branch {
+                Some(branch) => writeln!(final_writer, "- **Branch:** `{}`", branch)?,
+                None => writeln!(final_writer, "- **Branch:** _detached_")?,
+            }
+            writeln!(
+                final_writer,
+                "- **Dirty:** {}",
+                if dirty { "yes" } else { "no" }
+            )?;
+        }
+        writeln!(final_writer, "- **Recent commits:**")?;
+        for c in &info.recent_commits {
+            writeln!(final_writer, "  - `{}` {} {}", c.commit, c.date, c.subject)?;
+        }
+        writeln!(final_writer)?;
+        Ok(())
+    }
+
+    fn write_markdown_licenses(&self, final_writer: &mut dyn Write) -> Result<()> {
+        let groups = self.licenses.groups();
+        if groups.is_empty() {
+            return Ok(());
+        }
+        let files: usize = groups.iter().map(|g| g.files).sum();
+        writeln!(final_writer, "## License Headers\n")?;
+        writeln!(
+            final_writer,
+            "Removed from {} files; each distinct header is shown once.\n",
+            files
+        )?;
+        for group in groups {
+            writeln!(final_writer, "### {} ({} files)\n", group.spdx, group.files)?;
+            let fence = crate::processing::fence_for(&group.text);
+            writeln!(final_writer, "{}text\n{}\n{}\n", fence, group.text, fence)?;
+        }
+        Ok(())
+    }
 
+    fn write_markdown_toc(&self, final_writer: &mut dyn Write, part: usize) -> Result<()> {
+        writeln!(final_writer, "<a id=\"{}\"></a>", TOC_ANCHOR)?;
         writeln!(final_writer, "## Table of Contents\n")?;
-        for path in &self.included_paths {
-            let rel = path.strip_prefix(&self.root).unwrap_or(path);
+        // A `<pre>` block rather than a code fence, so the file names can link to their sections
+        if let Some(tree) = self.toc_tree(part, true) {
+            writeln!(final_writer, "<pre>\n{}</pre>\n", tree)?;
+            return Ok(());
+        }
+        for entry in &self.parts[part].entries {
             writeln!(
                 final_writer,
-                "- {}",
-                rel.to_string_lossy().replace('\\', "/")
+                "- [{}](#{}) ({} tokens{})",
+                link_text(&self.entry_label(&entry.path, entry.piece)),
+                entry.anchor,
+                entry.tokens,
+                score_detail(entry.score)
             )?;
         }
         writeln!(final_writer)?;
+        Ok(())
+    }
 
-        self.body_writer.flush()?;
-        let mut temp_file = self.body_writer.into_inner()?;
-        temp_file.seek(SeekFrom::Start(0))?;
-        io::copy(&mut temp_file, &mut final_writer)?;
+    /// The tree view of a part's TOC, unless the list style is selected. Omitted files are
+    /// only shown in an unsplit snapshot, where a single TOC covers everything. With `links`,
+    /// the tree is HTML with each file name linked to its section.
+    fn toc_tree(&self, part: usize, links: bool) -> Option<String> {
+        if self.toc == TocStyle::List {
+            return None;
+        }
+        let mut tree = Tree::default();
+        for entry in &self.parts[part].entries {
+            let detail = match entry.piece {
+                Some(p) => format!(
+                    " (lines {}-{}, piece {} of {}, {} tokens{})",
+                    p.first_line,
+                    p.last_line,
+                    p.index,
+                    p.count,
+                    entry.tokens,
+                    score_detail(entry.score)
+                ),
+                None => format!(" ({} tokens{})", entry.tokens, score_detail(entry.score)),
+            };
+            tree.add_file(
+                &self.rel_path_str(&entry.path),
+                &entry.anchor,
+                &detail,
+                entry.size,
+                entry.tokens,
+            );
+        }
+        if self.toc_omitted && !self.is_split() {
+            for (path, reason, _) in &self.omitted {
+                tree.add_omitted(&self.rel_path_str(path), reason);
+            }
+        }
+        let mut out = String::new();
+        for line in tree.render(self.toc, self.toc_depth, links) {
+            out.push_str(&line);
+            out.push('\n');
+        }
+        Some(out)
+    }
 
+    /// Discovery errors, omitted files and summary tables.
+    fn write_markdown_report(
+        &self,
+        final_writer: &mut dyn Write,
+        discovery_errors: &[String],
+    ) -> Result<()> {
         if !discovery_errors.is_empty() {
             writeln!(final_writer, "## Discovery Errors\n")?;
             for error in discovery_errors {
@@ -180,13 +996,12 @@ impl SnapshotWriter {
             writeln!(final_writer, "| Path | Size (MB) | Reason |")?;
             writeln!(final_writer, "|---|---:|---|")?;
             for (path, reason, size) in &self.omitted {
-                let rel = path.strip_prefix(&self.root).unwrap_or(path);
                 let mb = (*size as f64) / 1024.0 / 1024.0;
                 let clean_reason = reason.replace('|', "\\|");
                 writeln!(
                     final_writer,
                     "| {} | {:.2} | {} |",
-                    rel.to_string_lossy().replace('\\', "/"),
+                    self.rel_path_str(path),
                     mb,
                     clean_reason
                 )?;
@@ -208,29 +1023,600 @@ impl SnapshotWriter {
             self.total_bytes as f64 / 1024.0 / 1024.0
         )?;
         writeln!(final_writer, "- **Total lines:** {}", self.total_lines)?;
+        writeln!(
+            final_writer,
+            "- **Total tokens ({}):** {}",
+            self.tokenizer.name(),
+            self.total_tokens
+        )?;
+        if !self.secrets.is_empty() {
+            writeln!(
+                final_writer,
+                "- **Secrets redacted:** {}",
+                secrets::describe(&self.secrets)
+            )?;
+        }
 
         // Composition breakdown
         writeln!(final_writer, "\n### Composition\n")?;
-        writeln!(final_writer, "| Extension | Files | Size (MB) |")?;
-        writeln!(final_writer, "|---|---:|---:|")?;
+        writeln!(final_writer, "| Extension | Files | Size (MB) | Tokens |")?;
+        writeln!(final_writer, "|---|---:|---:|---:|")?;
         let mut sorted_stats: Vec<_> = self.stats_by_extension.iter().collect();
-        sorted_stats.sort_by(|a, b| b.1 .1.cmp(&a.1 .1));
+        sorted_stats.sort_by_key(|b| std::cmp::Reverse(b.1.bytes));
 
-        for (ext, (count, size)) in sorted_stats {
-            let mb = *size as f64 / 1024.0 / 1024.0;
-            writeln!(final_writer, "| .{} | {} | {:.2} |", ext, count, mb)?;
+        for (ext, stats) in sorted_stats {
+            let mb = stats.bytes as f64 / 1024.0 / 1024.0;
+            writeln!(
+                final_writer,
+                "| .{} | {} | {:.2} | {} |",
+                ext, stats.files, mb, stats.tokens
+            )?;
         }
 
-        final_writer.flush()?;
+        writeln!(final_writer, "\n### Languages\n")?;
+        writeln!(final_writer, "| Language | Files | Size (MB) | Tokens |")?;
+        writeln!(final_writer, "|---|---:|---:|---:|")?;
+        let mut sorted_stats: Vec<_> = self.stats_by_language.iter().collect();
+        sorted_stats.sort_by_key(|b| std::cmp::Reverse(b.1.bytes));
 
-        Ok(SnapshotStats {
-            output_path,
-            total_files: self.included_paths.len(),
+        for (language, stats) in sorted_stats {
+            let mb = stats.bytes as f64 / 1024.0 / 1024.0;
+            writeln!(
+                final_writer,
+                "| {} | {} | {:.2} | {} |",
+                language, stats.files, mb, stats.tokens
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn write_json(
+        &mut self,
+        final_writer: &mut dyn Write,
+        discovery_errors: &[String],
+    ) -> Result<()> {
+        // Hand-assembled envelope so the files array can be streamed from the spool
+        writeln!(final_writer, "{{")?;
+        self.write_json_header(final_writer)?;
+        self.write_json_files(final_writer, 0)?;
+        writeln!(final_writer, ",")?;
+        self.write_json_report(final_writer, discovery_errors)?;
+        writeln!(final_writer, "}}")?;
+
+        Ok(())
+    }
+
+    fn write_json_part(&mut self, final_writer: &mut dyn Write, part: usize) -> Result<()> {
+        writeln!(final_writer, "{{")?;
+        self.write_json_header(final_writer)?;
+        writeln!(final_writer, "\"part\": {},", part + 1)?;
+        writeln!(final_writer, "\"parts\": {},", self.parts.len())?;
+        self.write_json_files(final_writer, part)?;
+        writeln!(final_writer)?;
+        writeln!(final_writer, "}}")?;
+
+        Ok(())
+    }
+
+    fn write_json_index(
+        &mut self,
+        final_writer: &mut dyn Write,
+        part_names: &[String],
+        discovery_errors: &[String],
+    ) -> Result<()> {
+        let parts: Vec<JsonPart> = self
+            .parts
+            .iter()
+            .enumerate()
+            .map(|(idx, part)| JsonPart {
+                part: idx + 1,
+                file: part_names[idx].clone(),
+                bytes: part.bytes,
+                tokens: part.tokens,
+                files: part
+                    .entries
+                    .iter()
+                    .map(|e| self.rel_path_str(&e.path))
+                    .collect(),
+            })
+            .collect();
+
+        writeln!(final_writer, "{{")?;
+        self.write_json_header(final_writer)?;
+        writeln!(
+            final_writer,
+            "\"parts\": {},",
+            serde_json::to_string(&parts)?
+        )?;
+        self.write_json_report(final_writer, discovery_errors)?;
+        writeln!(final_writer, "}}")?;
+
+        Ok(())
+    }
+
+    fn write_json_header(&self, final_writer: &mut dyn Write) -> Result<()> {
+        writeln!(
+            final_writer,
+            "\"root\": {},",
+            serde_json::to_string(&self.display_root())?
+        )?;
+        writeln!(
+            final_writer,
+            "\"timestamp\": {},",
+            serde_json::to_string(&self.timestamp)?
+        )?;
+        if let Some((name, commit)) = &self.revision {
+            writeln!(
+                final_writer,
+                "\"revision\": {{\"ref\": {}, \"commit\": {}}},",
+                serde_json::to_string(name)?,
+                serde_json::to_string(commit)?
+            )?;
+        }
+        if let Some(info) = &self.repo_info {
+            writeln!(final_writer, "\"git\": {},", serde_json::to_string(info)?)?;
+        }
+        let licenses: Vec<JsonLicense> = self
+            .licenses
+            .groups()
+            .iter()
+            .map(|g| JsonLicense {
+                spdx: &g.spdx,
+                files: g.files,
+                text: &g.text,
+            })
+            .collect();
+        if !licenses.is_empty() {
+            writeln!(
+                final_writer,
+                "\"license_headers\": {},",
+                serde_json::to_string(&licenses)?
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the `files` array without a trailing separator.
+    fn write_json_files(&mut self, final_writer: &mut dyn Write, part: usize) -> Result<()> {
+        writeln!(final_writer, "\"files\": [")?;
+        self.copy_body(part, final_writer)?;
+        if !self.parts[part].entries.is_empty() {
+            writeln!(final_writer)?;
+        }
+        write!(final_writer, "]")?;
+        Ok(())
+    }
+
+    /// Writes `omitted`, `discovery_errors` and `stats`, the last members of the document.
+    fn write_json_report(
+        &self,
+        final_writer: &mut dyn Write,
+        discovery_errors: &[String],
+    ) -> Result<()> {
+        let omitted: Vec<JsonOmitted> = self
+            .omitted
+            .iter()
+            .map(|(path, reason, size)| JsonOmitted {
+                path: self.rel_path_str(path),
+                size: *size,
+                reason,
+            })
+            .collect();
+        writeln!(
+            final_writer,
+            "\"omitted\": {},",
+            serde_json::to_string(&omitted)?
+        )?;
+        writeln!(
+            final_writer,
+            "\"discovery_errors\": {},",
+            serde_json::to_string(discovery_errors)?
+        )?;
+
+        let stats = JsonStats {
+            files_included: self.included_paths.len(),
+            files_omitted: self.omitted.len(),
             total_bytes: self.total_bytes,
             total_lines: self.total_lines,
-            omitted_count: self.omitted.len(),
-            stats_by_extension: self.stats_by_extension,
-            top_offenders: self.top_offenders,
-        })
+            total_tokens: self.total_tokens,
+            tokenizer: self.tokenizer.name(),
+            secrets_redacted: &self.secrets,
+            by_extension: self
+                .stats_by_extension
+                .iter()
+                .map(|(ext, stats)| (ext.as_str(), *stats))
+                .collect(),
+            by_language: self
+                .stats_by_language
+                .iter()
+                .map(|(language, stats)| (*language, *stats))
+                .collect(),
+        };
+        writeln!(
+            final_writer,
+            "\"stats\": {}",
+            serde_json::to_string(&stats)?
+        )?;
+        Ok(())
+    }
+
+    fn write_xml(
+        &mut self,
+        final_writer: &mut dyn Write,
+        discovery_errors: &[String],
+    ) -> Result<()> {
+        writeln!(final_writer, "<snapshot>")?;
+        self.write_xml_header(final_writer)?;
+        self.write_xml_toc(final_writer, 0)?;
+
+        writeln!(final_writer, "<documents>")?;
+        self.copy_body(0, final_writer)?;
+        writeln!(final_writer, "</documents>")?;
+
+        self.write_xml_report(final_writer, discovery_errors)?;
+        writeln!(final_writer, "</snapshot>")?;
+
+        Ok(())
+    }
+
+    fn write_xml_part(&mut self, final_writer: &mut dyn Write, part: usize) -> Result<()> {
+        writeln!(
+            final_writer,
+            "<snapshot part=\"{}\" parts=\"{}\">",
+            part + 1,
+            self.parts.len()
+        )?;
+        self.write_xml_header(final_writer)?;
+        self.write_xml_toc(final_writer, part)?;
+
+        writeln!(final_writer, "<documents>")?;
+        self.copy_body(part, final_writer)?;
+        writeln!(final_writer, "</documents>")?;
+
+        writeln!(final_writer, "</snapshot>")?;
+
+        Ok(())
+    }
+
+    fn write_xml_index(
+        &mut self,
+        final_writer: &mut dyn Write,
+        part_names: &[String],
+        discovery_errors: &[String],
+    ) -> Result<()> {
+        writeln!(
+            final_writer,
+            "<snapshot_index parts=\"{}\">",
+            self.parts.len()
+        )?;
+        self.write_xml_header(final_writer)?;
+
+        writeln!(final_writer, "<parts>")?;
+        for (idx, part) in self.parts.iter().enumerate() {
+            writeln!(
+                final_writer,
+                "<part number=\"{}\" file=\"{}\" bytes=\"{}\" tokens=\"{}\">",
+                idx + 1,
+                xml_escape(&part_names[idx]),
+                part.bytes,
+                part.tokens
+            )?;
+            for entry in &part.entries {
+                writeln!(
+                    final_writer,
+                    "<file>{}</file>",
+                    xml_escape(&self.rel_path_str(&entry.path))
+                )?;
+            }
+            writeln!(final_writer, "</part>")?;
+        }
+        writeln!(final_writer, "</parts>")?;
+
+        self.write_xml_report(final_writer, discovery_errors)?;
+        writeln!(final_writer, "</snapshot_index>")?;
+
+        Ok(())
+    }
+
+    fn write_xml_header(&self, mut final_writer: &mut dyn Write) -> Result<()> {
+        writeln!(final_writer, "<header>")?;
+        writeln!(
+            final_writer,
+            "<base_path>{}</base_path>",
+            xml_escape(&self.display_root())
+        )?;
+        writeln!(
+            final_writer,
+            "<timestamp>{}</timestamp>",
+            xml_escape(&self.timestamp)
+        )?;
+        if let Some((name, commit)) = &self.revision {
+            writeln!(
+                final_writer,
+                "<revision ref=\"{}\">{}</revision>",
+                xml_escape(name),
+                xml_escape(commit)
+            )?;
+        }
+        if let Some(info) = &self.repo_info {
+            match info.dirty {
+                Some(dirty) => writeln!(final_writer, "<git dirty=\"{}\">", dirty)?,
+                None => writeln!(final_writer, "<git>")?,
+            }
+            writeln!(final_writer, "<head>{}</head>", xml_escape(&info.head))?;
+            if let Some(branch) = &info.branch {
+                writeln!(final_writer, "<branch>{}</branch>", xml_escape(branch))?;
+            }
+            writeln!(final_writer, "<recent_commits>")?;
+            for c in &info.recent_commits {
+                writeln!(
+                    final_writer,
+                    "<commit hash=\"{}\" date=\"{}\">{}</commit>",
+                    xml_escape(&c.commit),
+                    xml_escape(&c.date),
+                    xml_escape(&c.subject)
+                )?;
+            }
+            writeln!(final_writer, "</recent_commits>")?;
+            writeln!(final_writer, "</git>")?;
+        }
+        if !self.licenses.groups().is_empty() {
+            writeln!(final_writer, "<license_headers>")?;
+            for group in self.licenses.groups() {
+                writeln!(
+                    final_writer,
+                    "<license spdx=\"{}\" files=\"{}\">",
+                    xml_escape(&group.spdx),
+                    group.files
+                )?;
+                write_cdata(&mut final_writer, &group.text)?;
+                writeln!(final_writer, "</license>")?;
+            }
+            writeln!(final_writer, "</license_headers>")?;
+        }
+        writeln!(final_writer, "</header>")?;
+        Ok(())
+    }
+
+    fn write_xml_toc(&self, mut final_writer: &mut dyn Write, part: usize) -> Result<()> {
+        writeln!(final_writer, "<table_of_contents>")?;
+        if let Some(tree) = self.toc_tree(part, false) {
+            writeln!(final_writer, "<tree>")?;
+            write_cdata(&mut final_writer, &tree)?;
+            writeln!(final_writer, "</tree>")?;
+        }
+        for entry in &self.parts[part].entries {
+            let score = entry
+                .score
+                .map_or(String::new(), |s| format!(" score=\"{:.2}\"", s));
+            writeln!(
+                final_writer,
+                "<file tokens=\"{}\"{}>{}</file>",
+                entry.tokens,
+                score,
+                xml_escape(&self.entry_label(&entry.path, entry.piece))
+            )?;
+        }
+        writeln!(final_writer, "</table_of_contents>")?;
+        Ok(())
+    }
+
+    /// Discovery errors, omitted files and summary tags.
+    fn write_xml_report(
+        &self,
+        final_writer: &mut dyn Write,
+        discovery_errors: &[String],
+    ) -> Result<()> {
+        if !discovery_errors.is_empty() {
+            writeln!(final_writer, "<discovery_errors>")?;
+            for error in discovery_errors {
+                writeln!(final_writer, "<error>{}</error>", xml_escape(error))?;
+            }
+            writeln!(final_writer, "</discovery_errors>")?;
+        }
+
+        writeln!(final_writer, "<omitted>")?;
+        for (path, reason, size) in &self.omitted {
+            writeln!(
+                final_writer,
+                "<file size=\"{}\" reason=\"{}\">{}</file>",
+                size,
+                xml_escape(reason),
+                xml_escape(&self.rel_path_str(path))
+            )?;
+        }
+        writeln!(final_writer, "</omitted>")?;
+
+        writeln!(final_writer, "<summary>")?;
+        writeln!(
+            final_writer,
+            "<files_included>{}</files_included>",
+            self.included_paths.len()
+        )?;
+        writeln!(
+            final_writer,
+            "<files_omitted>{}</files_omitted>",
+            self.omitted.len()
+        )?;
+        writeln!(
+            final_writer,
+            "<total_bytes>{}</total_bytes>",
+            self.total_bytes
+        )?;
+        writeln!(
+            final_writer,
+            "<total_lines>{}</total_lines>",
+            self.total_lines
+        )?;
+        writeln!(
+            final_writer,
+            "<total_tokens tokenizer=\"{}\">{}</total_tokens>",
+            xml_escape(self.tokenizer.name()),
+            self.total_tokens
+        )?;
+        if !self.secrets.is_empty() {
+            writeln!(
+                final_writer,
+                "<secrets_redacted total=\"{}\">",
+                self.secrets.values().sum::<usize>()
+            )?;
+            for (kind, count) in &self.secrets {
+                writeln!(
+                    final_writer,
+                    "<secret kind=\"{}\" count=\"{}\"/>",
+                    kind, count
+                )?;
+            }
+            writeln!(final_writer, "</secrets_redacted>")?;
+        }
+
+        writeln!(final_writer, "<composition>")?;
+        let mut sorted_stats: Vec<_> = self.stats_by_extension.iter().collect();
+        sorted_stats.sort_by_key(|b| std::cmp::Reverse(b.1.bytes));
+        for (ext, stats) in sorted_stats {
+            writeln!(
+                final_writer,
+                "<type extension=\"{}\" files=\"{}\" bytes=\"{}\" tokens=\"{}\"/>",
+                xml_escape(ext),
+                stats.files,
+                stats.bytes,
+                stats.tokens
+            )?;
+        }
+        let mut sorted_stats: Vec<_> = self.stats_by_language.iter().collect();
+        sorted_stats.sort_by_key(|b| std::cmp::Reverse(b.1.bytes));
+        for (language, stats) in sorted_stats {
+            writeln!(
+                final_writer,
+                "<language name=\"{}\" files=\"{}\" bytes=\"{}\" tokens=\"{}\"/>",
+                xml_escape(language),
+                stats.files,
+                stats.bytes,
+                stats.tokens
+            )?;
+        }
+        writeln!(final_writer, "</composition>")?;
+        writeln!(final_writer, "</summary>")?;
+
+        Ok(())
+    }
+}
+
+/// Opens an output document, or stdout for dry runs.
+fn open_output(args: &Args, output_path: &Path) -> Result<Box<dyn Write>> {
+    if args.dry_run {
+        return Ok(Box::new(BufWriter::new(io::stdout())));
+    }
+
+    let file = if args.force {
+        File::create(output_path).context("Failed to create output file")?
+    } else {
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(output_path)
+            .context(format!(
+                "Output file exists: {:?}. Use --force.",
+                output_path
+            ))?
+    };
+
+    Ok(Box::new(BufWriter::with_capacity(64 * 1024, file)))
+}
+
+/// `out/merged.md` + `part1` -> `out/merged_part1.md`
+fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}_{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}_{}", stem, suffix),
+    };
+    path.with_file_name(name)
+}
+
+/// Anchor of the table of contents, the target of the "Back to top" links.
+const TOC_ANCHOR: &str = "table-of-contents";
+
+/// `, score X.XX` after a token count, when ranking by `--query`.
+fn score_detail(score: Option<f64>) -> String {
+    score.map_or(String::new(), |s| format!(", score {:.2}", s))
+}
+
+/// Scores in JSON carry the two decimals shown in the TOC.
+fn round_score(score: f64) -> f64 {
+    (score * 100.0).round() / 100.0
+}
+
+/// Lowercase ASCII letters and digits, with every other run of characters as one `-`.
+fn slug(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            out.push(c.to_ascii_lowercase());
+        } else if !out.is_empty() && !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    out.truncate(out.trim_end_matches('-').len());
+    out
+}
+
+/// Escapes the characters that would end or break a Markdown link text.
+fn link_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '[' | ']' | '`') {
+            out.push('\\');
+        }
+        out.push(c);
     }
+    out
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Escapes text for use in XML element content and attribute values.
+fn xml_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c if !is_xml_char(c) => out.push('\u{FFFD}'),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Writes `content` as CDATA, followed by a newline outside the section so the
+/// content reads back unchanged.
+/// `]]>` cannot appear inside a section, so it is split across two sections.
+fn write_cdata(writer: &mut impl Write, content: &str) -> Result<()> {
+    let safe: String = content
+        .chars()
+        .map(|c| if is_xml_char(c) { c } else { '\u{FFFD}' })
+        .collect();
+    writeln!(
+        writer,
+        "<![CDATA[{}]]>",
+        safe.replace("]]>", "]]]]><![CDATA[>")
+    )?;
+    Ok(())
+}
+
+/// Characters allowed by the XML 1.0 `Char` production.
+fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}' | '\u{10000}'..)
 }
@@ -0,0 +1,45 @@
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+
+/// Applies `f` to every item on up to `jobs` threads and returns the results in input order.
+pub fn map_ordered<T, R, F>(items: Vec<T>, jobs: usize, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+{
+    let len = items.len();
+    if jobs <= 1 || len <= 1 {
+        return items.into_iter().map(f).collect();
+    }
+
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let (tx, rx) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(len) {
+            let tx = tx.clone();
+            let queue = &queue;
+            let f = &f;
+            scope.spawn(move || loop {
+                let next = queue.lock().expect("work queue poisoned").next();
+                let Some((idx, item)) = next else {
+                    break;
+                };
+                if tx.send((idx, f(item))).is_err() {
+                    break;
+                }
+            });
+        }
+    });
+    drop(tx);
+
+    let mut results: Vec<Option<R>> = (0..len).map(|_| None).collect();
+    for (idx, result) in rx {
+        results[idx] = Some(result);
+    }
+    results
+        .into_iter()
+        .map(|r| r.expect("every item is processed"))
+        .collect()
+}
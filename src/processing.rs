@@ -1,10 +1,27 @@
+use crate::comments::{self, CommentMode};
+use crate::compact;
 use crate::config::AppConfig;
+use crate::git::{ChangeSet, DiffMode, Revision};
+use crate::language;
+use crate::license::{self, Headers, LicenseMode};
+use crate::lines::LineMap;
+use crate::relevance::{DocStats, Query};
+use crate::secrets::{Redactions, Scanner};
 use std::fs::File;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 const SAMPLE_SIZE: usize = 8 * 1024;
 const CONTROL_CHAR_THRESHOLD: f64 = 0.02;
+/// Larger files skip comment and license header stripping and compaction to maintain throughput.
+const MAX_STRIP_SIZE: u64 = 1024 * 1024;
+
+/// State prepared once per run and shared by every file.
+#[derive(Default)]
+pub struct Shared {
+    pub headers: Headers,
+    pub secrets: Scanner,
+}
 
 #[derive(Debug)]
 pub enum FileStatus {
@@ -12,6 +29,15 @@ pub enum FileStatus {
         path: PathBuf,
         content: String,
         size: u64,
+        tokens: u64,
+        /// Canonical language name from `language::detect`.
+        language: Option<&'static str>,
+        /// Unified diff emitted after the content (`--diff append`).
+        diff: Option<String>,
+        /// License header removed under `--license-headers header`, listed in the snapshot header.
+        license: Option<String>,
+        /// Secrets redacted from the content and diff.
+        secrets: Redactions,
     },
     Omitted {
         path: PathBuf,
@@ -20,7 +46,21 @@ pub enum FileStatus {
     },
 }
 
-pub fn process_file(path: PathBuf, config: &AppConfig) -> FileStatus {
+impl FileStatus {
+    pub fn path(&self) -> &Path {
+        match self {
+            FileStatus::Included { path, .. } | FileStatus::Omitted { path, .. } => path,
+        }
+    }
+
+    pub fn into_path(self) -> PathBuf {
+        match self {
+            FileStatus::Included { path, .. } | FileStatus::Omitted { path, .. } => path,
+        }
+    }
+}
+
+pub fn process_file(path: PathBuf, config: &AppConfig, shared: &Shared) -> FileStatus {
     let metadata = match std::fs::metadata(&path) {
         Ok(m) => m,
         Err(e) => {
@@ -36,23 +76,21 @@ pub fn process_file(path: PathBuf, config: &AppConfig) -> FileStatus {
     let max_bytes = config.max_file_mb.saturating_mul(1024 * 1024);
 
     // Initial check based on metadata
-    if size > max_bytes {
-        return FileStatus::Omitted {
-            path,
-            reason: format!(
-                "Size {} MB exceeds limit of {} MB",
-                size / 1024 / 1024,
-                config.max_file_mb
-            ),
-            size,
-        };
+    if let Some(reason) = size_limit_reason(size, config) {
+        return FileStatus::Omitted { path, reason, size };
     }
 
     if size == 0 {
+        let language = language::detect(&path, "").map(|l| l.name);
         return FileStatus::Included {
             path,
             content: String::new(),
             size: 0,
+            tokens: 0,
+            language,
+            diff: None,
+            license: None,
+            secrets: Redactions::new(),
         };
     }
 
@@ -93,6 +131,132 @@ pub fn process_file(path: PathBuf, config: &AppConfig) -> FileStatus {
         };
     }
 
+    process_bytes(path, full_buffer, config, shared)
+}
+
+/// Reads a file from the tree of a git revision instead of the filesystem.
+/// `path` is the virtual path returned by `find_files_in_revision`.
+pub fn process_blob(
+    path: PathBuf,
+    root: &Path,
+    revision: &Revision,
+    config: &AppConfig,
+    shared: &Shared,
+) -> FileStatus {
+    let rel = crate::clean_path(path.strip_prefix(root).unwrap_or(&path));
+    let size = revision.size(&rel).unwrap_or(0);
+
+    // The tree records blob sizes, so oversized files are never read
+    if let Some(reason) = size_limit_reason(size, config) {
+        return FileStatus::Omitted { path, reason, size };
+    }
+
+    match revision.read_path(&rel) {
+        Ok(buffer) => process_bytes(path, buffer, config, shared),
+        Err(e) => FileStatus::Omitted {
+            path,
+            reason: format!("Failed to read blob: {:#}", e),
+            size,
+        },
+    }
+}
+
+fn size_limit_reason(size: u64, config: &AppConfig) -> Option<String> {
+    let max_bytes = config.max_file_mb.saturating_mul(1024 * 1024);
+    (size > max_bytes).then(|| {
+        format!(
+            "Size {} MB exceeds limit of {} MB",
+            size / 1024 / 1024,
+            config.max_file_mb
+        )
+    })
+}
+
+/// Unmarked leading comment blocks of a file, read from its first bytes, for `Headers::scan`.
+/// `path` is a virtual path under `root` when reading from `revision`.
+pub fn license_candidates(path: &Path, root: &Path, revision: Option<&Revision>) -> Vec<String> {
+    let head = match revision {
+        Some(revision) => {
+            let rel = crate::clean_path(path.strip_prefix(root).unwrap_or(path));
+            revision.read_path(&rel).ok().map(|mut buffer| {
+                buffer.truncate(SAMPLE_SIZE);
+                buffer
+            })
+        }
+        None => File::open(path).ok().and_then(|f| {
+            let mut buffer = Vec::with_capacity(SAMPLE_SIZE);
+            f.take(SAMPLE_SIZE as u64)
+                .read_to_end(&mut buffer)
+                .ok()
+                .map(|_| buffer)
+        }),
+    };
+    let Some(head) = head.filter(|h| is_mostly_text(h)) else {
+        return Vec::new();
+    };
+    let head = String::from_utf8_lossy(&head);
+    match language::detect(path, &head).and_then(|l| comments::syntax_for(l.name)) {
+        Some(syntax) => license::unmarked_blocks(&head, syntax),
+        None => Vec::new(),
+    }
+}
+
+/// Lossy text of a file for ranking and import scanning, or `None` when it is missing,
+/// binary or over `max_file_mb`. `path` is a virtual path under `root` when reading from `revision`.
+pub fn read_text(
+    path: &Path,
+    root: &Path,
+    revision: Option<&Revision>,
+    config: &AppConfig,
+) -> Option<String> {
+    let max_bytes = config.max_file_mb.saturating_mul(1024 * 1024);
+    let buffer = match revision {
+        Some(revision) => {
+            let rel = crate::clean_path(path.strip_prefix(root).unwrap_or(path));
+            revision
+                .size(&rel)
+                .filter(|&size| size <= max_bytes)
+                .and_then(|_| revision.read_path(&rel).ok())?
+        }
+        None => {
+            let mut buffer = Vec::new();
+            File::open(path)
+                .ok()?
+                .take(max_bytes.saturating_add(1))
+                .read_to_end(&mut buffer)
+                .ok()?;
+            buffer
+        }
+    };
+    if buffer.len() as u64 > max_bytes || !is_mostly_text(&buffer[..buffer.len().min(SAMPLE_SIZE)])
+    {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+/// Query term counts of a file's relative path and text, for ranking under `--query`.
+/// Binary files and files over `max_file_mb` are ranked by their path alone.
+pub fn query_stats(
+    path: &Path,
+    root: &Path,
+    revision: Option<&Revision>,
+    config: &AppConfig,
+    query: &Query,
+) -> DocStats {
+    let rel = crate::clean_path(path.strip_prefix(root).unwrap_or(path));
+    let text = read_text(path, root, revision, config).unwrap_or_default();
+    query.stats(&rel, &text)
+}
+
+/// Binary check, decoding, secret redaction, license and comment stripping, compaction
+/// and token limits for raw file bytes.
+fn process_bytes(
+    path: PathBuf,
+    full_buffer: Vec<u8>,
+    config: &AppConfig,
+    shared: &Shared,
+) -> FileStatus {
     // Check binary on the slice of the buffer
     let sample_len = std::cmp::min(SAMPLE_SIZE, full_buffer.len());
     if !is_mostly_text(&full_buffer[..sample_len]) {
@@ -133,99 +297,166 @@ pub fn process_file(path: PathBuf, config: &AppConfig) -> FileStatus {
         cow.into_owned()
     };
 
+    // Detect before any rewrite, while the shebang and modelines are in place
+    let language = language::detect(&path, &content).map(|l| l.name);
+
+    // Redact first, so no later step can move a secret out of reach of the detectors
+    let mut lines = config.line_numbers.then(|| LineMap::new(&content));
+    let mut secrets = Redactions::new();
+    let kept;
+    (content, kept) = shared.secrets.redact(&content, &mut secrets);
+    retain(&mut lines, &kept);
+
+    let syntax = language
+        .and_then(comments::syntax_for)
+        .filter(|_| (full_buffer.len() as u64) < MAX_STRIP_SIZE);
+
+    // Remove the license header before the comments, so the SPDX marker survives
+    let mut license = None;
+    if let Some(syntax) = syntax.filter(|_| config.license_headers != LicenseMode::Keep) {
+        let kept;
+        (content, kept, license) = license::remove(&content, syntax, &shared.headers);
+        retain(&mut lines, &kept);
+    }
+
     // Remove comments
-    const MAX_STRIP_SIZE: u64 = 1024 * 1024;
-    if config.remove_comments && (full_buffer.len() as u64) < MAX_STRIP_SIZE {
-        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-        content = strip_comments(&content, ext);
+    if let Some(syntax) = syntax.filter(|_| config.comment_mode != CommentMode::None) {
+        let kept;
+        (content, kept) = comments::strip(&content, syntax, config.comment_mode);
+        retain(&mut lines, &kept);
+    }
+
+    if let (Some(syntax), Some(header)) = (syntax, &license) {
+        if config.license_headers == LicenseMode::Spdx {
+            let at;
+            (content, at) = license::insert_marker(&content, syntax, header);
+            if let Some(map) = &mut lines {
+                map.insert(at);
+            }
+        }
+    }
+    let license = license.filter(|_| config.license_headers == LicenseMode::Header);
+
+    if config.compact && (full_buffer.len() as u64) < MAX_STRIP_SIZE {
+        let kept;
+        (content, kept) = compact::compact(&content, language.unwrap_or(""), config.compact_indent);
+        retain(&mut lines, &kept);
     }
 
+    // Number last, so the gutter counts toward tokens and the fence sees it
+    if let Some(map) = &lines {
+        content = map.number(&content);
+    }
+
+    // `max_file_tokens` is applied by `limit_tokens`, once any diff is attached
+    let tokens = config.tokenizer.count(&content);
+
     FileStatus::Included {
         path,
         content,
         size: full_buffer.len() as u64,
+        tokens,
+        language,
+        diff: None,
+        license,
+        secrets,
     }
 }
 
-static RE_C: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
-static RE_HASH: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
-static RE_DASH: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
-static RE_XML: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
-
-/// Removes comments based on file extension to avoid cross-language syntax corruption.
-fn strip_comments(content: &str, ext: &str) -> String {
-    let ext_lower = ext.to_lowercase();
-
-    enum Style {
-        C,
-        Hash,
-        Dash,
-        Xml,
-        None,
-    }
-
-    let style = match ext_lower.as_str() {
-        "rs" | "c" | "cpp" | "h" | "hpp" | "js" | "ts" | "java" | "go" | "kt" | "swift" | "css"
-        | "cs" | "php" => Style::C,
-        "py" | "sh" | "rb" | "yaml" | "yml" | "toml" | "dockerfile" | "pl" | "ps1" => Style::Hash,
-        "sql" | "lua" | "hs" => Style::Dash,
-        "html" | "xml" | "vue" | "svelte" => Style::Xml,
-        _ => Style::None,
+/// Adds the git diff of an included file, or replaces its content with it.
+/// Runs after `process_file` so changed files still pass the binary and size checks.
+pub fn attach_diff(
+    status: FileStatus,
+    changes: &ChangeSet,
+    mode: DiffMode,
+    root: &Path,
+    config: &AppConfig,
+    shared: &Shared,
+) -> FileStatus {
+    let FileStatus::Included {
+        path,
+        content,
+        size,
+        tokens,
+        language,
+        license,
+        secrets,
+        ..
+    } = status
+    else {
+        return status;
     };
 
-    match style {
-        Style::C => {
-            // Match strings (double, single) OR comments (block, line)
-            // Groups: 1=double quote string, 2=single quote string, 3=block comment, 4=line comment
-            let re = RE_C.get_or_init(|| {
-                regex::Regex::new(r#"(?m)"(\\.|[^"\\])*"|'(\\.|[^'\\])*'|(/\*[\s\S]*?\*/)|(//.*)$"#)
-                    .unwrap()
-            });
-            re.replace_all(content, |caps: &regex::Captures| {
-                // Check if captured groups are comments (group 3 or 4)
-                if caps.get(3).is_some() || caps.get(4).is_some() {
-                    "".to_string()
-                } else {
-                    caps.get(0).unwrap().as_str().to_string()
-                }
-            })
-            .into_owned()
-        }
-        Style::Hash => {
-            // Groups: 1=double quote string, 2=single quote string, 3=hash comment
-            let re = RE_HASH.get_or_init(|| {
-                regex::Regex::new(r#"(?m)"(\\.|[^"\\])*"|'(\\.|[^'\\])*'|(#.*)$"#).unwrap()
-            });
-            re.replace_all(content, |caps: &regex::Captures| {
-                if caps.get(3).is_some() {
-                    "".to_string()
-                } else {
-                    caps.get(0).unwrap().as_str().to_string()
-                }
-            })
-            .into_owned()
-        }
-        Style::Dash => {
-            // Groups: 1=double quote string, 2=single quote string, 3=dash comment
-            let re = RE_DASH.get_or_init(|| {
-                regex::Regex::new(r#"(?m)"(\\.|[^"\\])*"|'(\\.|[^'\\])*'|(--.*)$"#).unwrap()
-            });
-            re.replace_all(content, |caps: &regex::Captures| {
-                if caps.get(3).is_some() {
-                    "".to_string()
-                } else {
-                    caps.get(0).unwrap().as_str().to_string()
-                }
-            })
-            .into_owned()
-        }
-        Style::Xml => {
-            let re = RE_XML.get_or_init(|| regex::Regex::new(r#"(?s)<!--.*?-->"#).unwrap());
-            re.replace_all(content, |_caps: &regex::Captures| "".to_string())
-                .into_owned()
+    let rel = crate::clean_path(path.strip_prefix(root).unwrap_or(&path));
+    let diff = match changes.diff(root, &rel) {
+        Ok(d) => d,
+        Err(e) => {
+            return FileStatus::Omitted {
+                path,
+                reason: format!("Failed to read git diff: {}", e),
+                size,
+            };
         }
-        Style::None => content.to_string(),
+    };
+
+    let mut diff_secrets = Redactions::new();
+    let (diff, _) = shared.secrets.redact(&diff, &mut diff_secrets);
+    let diff_tokens = config.tokenizer.count(&diff);
+    match mode {
+        DiffMode::Append => FileStatus::Included {
+            path,
+            content,
+            size,
+            tokens: tokens + diff_tokens,
+            language,
+            diff: Some(diff),
+            license,
+            secrets: merge_redactions(secrets, diff_secrets),
+        },
+        DiffMode::Only => FileStatus::Included {
+            path,
+            content: diff,
+            size,
+            tokens: diff_tokens,
+            language,
+            diff: None,
+            license,
+            secrets: diff_secrets,
+        },
+    }
+}
+
+/// Omits an included file whose emitted text, content and any appended diff, exceeds
+/// `max_file_tokens`. Runs after `attach_diff`, so `--diff only` is measured by the diff.
+pub fn limit_tokens(status: FileStatus, config: &AppConfig) -> FileStatus {
+    match status {
+        FileStatus::Included {
+            path, size, tokens, ..
+        } if config.max_file_tokens.is_some_and(|max| tokens > max) => FileStatus::Omitted {
+            path,
+            reason: format!(
+                "{} tokens exceeds limit of {} tokens",
+                tokens,
+                config.max_file_tokens.unwrap_or_default()
+            ),
+            size,
+        },
+        status => status,
+    }
+}
+
+/// Applies a transform's kept lines to the line map, when numbering lines.
+fn retain(lines: &mut Option<LineMap>, kept: &[usize]) {
+    if let Some(map) = lines {
+        map.retain(kept);
+    }
+}
+
+fn merge_redactions(mut a: Redactions, b: Redactions) -> Redactions {
+    for (kind, count) in b {
+        *a.entry(kind).or_default() += count;
     }
+    a
 }
 
 fn is_mostly_text(sample: &[u8]) -> bool {
@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+
+/// Order of the files in a `--query` snapshot.
+#[derive(Serialize, Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum QueryOrder {
+    /// Discovery (path) order, as without a query.
+    #[default]
+    Path,
+    /// Highest score first.
+    Relevance,
+}
+
+/// BM25 term frequency saturation.
+const K1: f64 = 1.2;
+/// BM25 document length normalization.
+const B: f64 = 0.75;
+/// Each path term counts as this many content occurrences, so `auth/session.rs`
+/// ranks high for "session" even when the word is rare inside it.
+const PATH_WEIGHT: u32 = 5;
+
+/// Query words that carry no meaning for ranking.
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "do", "does", "for", "from", "how", "in",
+    "is", "it", "of", "on", "or", "the", "this", "that", "to", "what", "when", "where", "which",
+    "why", "with",
+];
+
+/// Lowercase terms of a text. Identifiers are also split into their words, so
+/// `parseHttpRequest` yields `parsehttprequest`, `parse`, `http` and `request`.
+fn terms(text: &str, mut f: impl FnMut(String)) {
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        if word.is_empty() {
+            continue;
+        }
+        let parts = split_identifier(word);
+        if parts.len() > 1 {
+            emit(word, &mut f);
+        }
+        for part in parts {
+            emit(part, &mut f);
+        }
+    }
+}
+
+fn emit(word: &str, f: &mut impl FnMut(String)) {
+    if word.len() < 2 || word.chars().all(|c| c.is_ascii_digit()) {
+        return;
+    }
+    f(stem(&word.to_lowercase()));
+}
+
+/// `parseHTTPRequest2` -> `parse`, `HTTP`, `Request2`.
+fn split_identifier(word: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = word.char_indices().collect();
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for i in 1..chars.len() {
+        let (at, c) = chars[i];
+        let prev = chars[i - 1].1;
+        let next_lower = chars.get(i + 1).is_some_and(|&(_, n)| n.is_lowercase());
+        // A new word starts at `aB`, and at the last capital of `HTTPRequest`
+        if c.is_uppercase() && (prev.is_lowercase() || (prev.is_uppercase() && next_lower)) {
+            parts.push(&word[start..at]);
+            start = at;
+        }
+    }
+    parts.push(&word[start..]);
+    parts
+}
+
+/// Drops a plural `s`, so `handlers` matches `handler`.
+fn stem(word: &str) -> String {
+    match word.strip_suffix('s') {
+        Some(base) if base.len() >= 3 && !base.ends_with('s') => base.to_string(),
+        _ => word.to_string(),
+    }
+}
+
+/// The terms of a `--query`.
+#[derive(Debug)]
+pub struct Query {
+    terms: Vec<String>,
+}
+
+/// Query term counts and length of one file.
+#[derive(Debug, Clone, Default)]
+pub struct DocStats {
+    len: u64,
+    counts: Vec<u32>,
+}
+
+impl Query {
+    pub fn new(text: &str) -> Self {
+        let mut query = Vec::new();
+        terms(text, |term| {
+            if !STOP_WORDS.contains(&term.as_str()) && !query.contains(&term) {
+                query.push(term);
+            }
+        });
+        Self { terms: query }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Counts the query terms in a file's relative path and content.
+    pub fn stats(&self, path: &str, content: &str) -> DocStats {
+        let mut stats = DocStats {
+            len: 0,
+            counts: vec![0; self.terms.len()],
+        };
+        let mut count = |term: String, weight: u32| {
+            stats.len += u64::from(weight);
+            if let Some(idx) = self.terms.iter().position(|t| *t == term) {
+                stats.counts[idx] += weight;
+            }
+        };
+        terms(path, |term| count(term, PATH_WEIGHT));
+        terms(content, |term| count(term, 1));
+        stats
+    }
+}
+
+/// BM25 score of every file, in the order of `docs`.
+pub fn scores(docs: &[DocStats]) -> Vec<f64> {
+    let n = docs.len() as f64;
+    let avg_len = docs.iter().map(|d| d.len as f64).sum::<f64>() / n.max(1.0);
+    let terms = docs.first().map_or(0, |d| d.counts.len());
+    let idf: Vec<f64> = (0..terms)
+        .map(|t| {
+            let df = docs.iter().filter(|d| d.counts[t] > 0).count() as f64;
+            (1.0 + (n - df + 0.5) / (df + 0.5)).ln()
+        })
+        .collect();
+    docs.iter()
+        .map(|doc| {
+            let norm = K1 * (1.0 - B + B * doc.len as f64 / avg_len.max(1.0));
+            doc.counts
+                .iter()
+                .zip(&idf)
+                .map(|(&tf, idf)| {
+                    let tf = f64::from(tf);
+                    idf * tf * (K1 + 1.0) / (tf + norm)
+                })
+                .sum()
+        })
+        .collect()
+}
@@ -0,0 +1,257 @@
+use serde::{Deserialize, Serialize};
+
+/// How the table of contents lists files.
+#[derive(Serialize, Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum TocStyle {
+    /// One line per file with its relative path.
+    #[default]
+    List,
+    /// Directory tree drawn with Unicode box characters.
+    Tree,
+    /// Directory tree drawn with ASCII characters.
+    Ascii,
+}
+
+/// Branch, last branch, continuation and blank prefixes of a tree style.
+fn glyphs(style: TocStyle) -> [&'static str; 4] {
+    match style {
+        TocStyle::Ascii => ["|-- ", "`-- ", "|   ", "    "],
+        _ => ["├── ", "└── ", "│   ", "    "],
+    }
+}
+
+/// Included files, their sizes and tokens, and omitted files below a directory.
+#[derive(Debug, Default, Clone, Copy)]
+struct Totals {
+    files: usize,
+    bytes: u64,
+    tokens: u64,
+    omitted: usize,
+}
+
+impl Totals {
+    fn describe(&self) -> String {
+        let mut out = format!(
+            "{} {}, {:.2} MB, {} tokens",
+            self.files,
+            if self.files == 1 { "file" } else { "files" },
+            self.bytes as f64 / 1024.0 / 1024.0,
+            self.tokens
+        );
+        if self.omitted > 0 {
+            out.push_str(&format!(", {} omitted", self.omitted));
+        }
+        out
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Dir {
+        name: String,
+        totals: Totals,
+        children: Vec<Node>,
+    },
+    File {
+        name: String,
+        /// Token counts or omission reason after the name.
+        detail: String,
+        /// Section anchor the name links to.
+        anchor: Option<String>,
+    },
+}
+
+impl Node {
+    /// Adds `totals` to a directory and returns its children.
+    fn count(&mut self, totals: Totals) -> &mut Vec<Node> {
+        let Node::Dir {
+            totals: dir_totals,
+            children,
+            ..
+        } = self
+        else {
+            unreachable!("only directories are counted");
+        };
+        dir_totals.files += totals.files;
+        dir_totals.bytes += totals.bytes;
+        dir_totals.tokens += totals.tokens;
+        dir_totals.omitted += totals.omitted;
+        children
+    }
+}
+
+/// Directory tree of the files in a table of contents, in the order they were added.
+#[derive(Debug)]
+pub struct Tree {
+    root: Node,
+    last_path: Option<String>,
+}
+
+impl Default for Tree {
+    fn default() -> Self {
+        Self {
+            root: Node::Dir {
+                name: ".".to_string(),
+                totals: Totals::default(),
+                children: Vec::new(),
+            },
+            last_path: None,
+        }
+    }
+}
+
+impl Tree {
+    /// Adds an included file or a piece of one, linked to `anchor`. `detail` follows the
+    /// file name, and consecutive pieces of the same `path` count once toward the directory totals.
+    pub fn add_file(&mut self, path: &str, anchor: &str, detail: &str, size: u64, tokens: u64) {
+        let first = self.last_path.as_deref() != Some(path);
+        self.last_path = Some(path.to_string());
+        let totals = Totals {
+            files: usize::from(first),
+            bytes: if first { size } else { 0 },
+            tokens,
+            omitted: 0,
+        };
+        let file = Node::File {
+            name: path.rsplit('/').next().unwrap_or(path).to_string(),
+            detail: detail.to_string(),
+            anchor: Some(anchor.to_string()),
+        };
+        self.insert(path, totals, file);
+    }
+
+    /// Adds an omitted file, marked with the reason it was left out.
+    pub fn add_omitted(&mut self, path: &str, reason: &str) {
+        self.last_path = None;
+        let totals = Totals {
+            omitted: 1,
+            ..Totals::default()
+        };
+        let file = Node::File {
+            name: path.rsplit('/').next().unwrap_or(path).to_string(),
+            detail: format!(" [omitted: {}]", reason),
+            anchor: None,
+        };
+        self.insert(path, totals, file);
+    }
+
+    fn insert(&mut self, path: &str, totals: Totals, file: Node) {
+        let mut dirs: Vec<&str> = path.split('/').collect();
+        dirs.pop();
+        let mut children = self.root.count(totals);
+        for dir in dirs {
+            let at = match children
+                .iter()
+                .position(|c| matches!(c, Node::Dir { name, .. } if name == dir))
+            {
+                Some(at) => at,
+                None => {
+                    children.push(Node::Dir {
+                        name: dir.to_string(),
+                        totals: Totals::default(),
+                        children: Vec::new(),
+                    });
+                    children.len() - 1
+                }
+            };
+            children = children[at].count(totals);
+        }
+        children.push(file);
+    }
+
+    /// Renders one line per node. Directories `depth` levels below the root are
+    /// shown by their summary line alone. With `links`, the lines are HTML for a
+    /// `<pre>` block and file names link to their sections.
+    pub fn render(&self, style: TocStyle, depth: Option<usize>, links: bool) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Node::Dir {
+            name,
+            totals,
+            children,
+        } = &self.root
+        {
+            let view = View {
+                glyphs: glyphs(style),
+                depth,
+                links,
+            };
+            lines.push(format!("{}/ ({})", view.text(name), totals.describe()));
+            render_children(children, "", 1, &view, &mut lines);
+        }
+        lines
+    }
+}
+
+/// Rendering options shared by every level of the tree.
+struct View {
+    glyphs: [&'static str; 4],
+    depth: Option<usize>,
+    links: bool,
+}
+
+impl View {
+    fn text(&self, text: &str) -> String {
+        if !self.links {
+            return text.to_string();
+        }
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+}
+
+fn render_children(
+    children: &[Node],
+    prefix: &str,
+    level: usize,
+    view: &View,
+    lines: &mut Vec<String>,
+) {
+    let [branch, last_branch, pipe, blank] = view.glyphs;
+    for (idx, child) in children.iter().enumerate() {
+        let last = idx + 1 == children.len();
+        let connector = if last { last_branch } else { branch };
+        match child {
+            Node::File {
+                name,
+                detail,
+                anchor,
+            } => {
+                let name = match anchor {
+                    Some(anchor) if view.links => {
+                        format!("<a href=\"#{}\">{}</a>", anchor, view.text(name))
+                    }
+                    _ => view.text(name),
+                };
+                lines.push(format!(
+                    "{}{}{}{}",
+                    prefix,
+                    connector,
+                    name,
+                    view.text(detail)
+                ));
+            }
+            Node::Dir {
+                name,
+                totals,
+                children,
+            } => {
+                let collapsed = view.depth.is_some_and(|d| level >= d);
+                lines.push(format!(
+                    "{}{}{}/ ({}){}",
+                    prefix,
+                    connector,
+                    view.text(name),
+                    totals.describe(),
+                    if collapsed { " ..." } else { "" }
+                ));
+                if !collapsed {
+                    let prefix = format!("{}{}", prefix, if last { blank } else { pipe });
+                    render_children(children, &prefix, level + 1, view, lines);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use tiktoken_rs::CoreBPE;
+
+static CL100K: OnceLock<Option<CoreBPE>> = OnceLock::new();
+
+// The BPE merge is quadratic in piece length and the pre-tokenizer regex
+// overflows its backtracking stack on very long runs, so input is encoded in
+// line-aligned segments. Boundary effects cost a handful of tokens per segment.
+const SEGMENT_BYTES: usize = 8 * 1024;
+// Lines longer than this (minified code, data blobs) are hard split.
+const LONG_LINE_BYTES: usize = 1024;
+const LONG_LINE_CHUNK: usize = 256;
+
+/// Tokenizer profile used to estimate how much model context a file consumes.
+#[derive(Serialize, Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Tokenizer {
+    /// cl100k_base BPE (GPT-4 family). Ranks are embedded, no network access.
+    #[default]
+    Cl100k,
+    /// Cheap heuristic: one token per four characters.
+    Chars4,
+}
+
+impl Tokenizer {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tokenizer::Cl100k => "cl100k",
+            Tokenizer::Chars4 => "chars/4",
+        }
+    }
+
+    /// Estimates the number of tokens in `text`.
+    pub fn count(&self, text: &str) -> u64 {
+        if text.is_empty() {
+            return 0;
+        }
+        match self {
+            Tokenizer::Cl100k => {
+                // The embedded ranks are static, so a failure here is a build problem.
+                // Fall back to the heuristic rather than aborting the snapshot.
+                match CL100K.get_or_init(|| tiktoken_rs::cl100k_base().ok()) {
+                    Some(bpe) => segments(text)
+                        .into_iter()
+                        .map(|seg| bpe.encode_ordinary(seg).len() as u64)
+                        .sum(),
+                    None => chars4(text),
+                }
+            }
+            Tokenizer::Chars4 => chars4(text),
+        }
+    }
+}
+
+fn chars4(text: &str) -> u64 {
+    (text.chars().count() as u64).div_ceil(4)
+}
+
+/// Splits `text` into contiguous segments for the BPE encoder.
+///
+/// Pre-tokenizer pieces only cross line boundaries through whitespace, so
+/// segments are cut on line boundaries, additionally before whitespace runs
+/// grow long, and overlong lines are hard split.
+fn segments(text: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    let mut end = 0;
+    // Length of the whitespace run at the end of the current segment
+    let mut ws_run = 0;
+
+    for line in text.split_inclusive('\n') {
+        if line.len() > LONG_LINE_BYTES {
+            if end > start {
+                out.push(&text[start..end]);
+            }
+            let mut rest = line;
+            while !rest.is_empty() {
+                let mut cut = rest.len().min(LONG_LINE_CHUNK);
+                while !rest.is_char_boundary(cut) {
+                    cut += 1;
+                }
+                out.push(&rest[..cut]);
+                rest = &rest[cut..];
+            }
+            end += line.len();
+            start = end;
+            ws_run = 0;
+            continue;
+        }
+
+        let trailing_ws = line.len() - line.trim_end().len();
+        let blank = trailing_ws == line.len();
+        if end > start
+            && (end - start + line.len() > SEGMENT_BYTES
+                || (blank && ws_run + line.len() > LONG_LINE_CHUNK))
+        {
+            out.push(&text[start..end]);
+            start = end;
+            ws_run = 0;
+        }
+        end += line.len();
+        ws_run = if blank {
+            ws_run + line.len()
+        } else {
+            trailing_ws
+        };
+    }
+    if end > start {
+        out.push(&text[start..end]);
+    }
+    out
+}
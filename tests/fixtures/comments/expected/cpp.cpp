@@ -0,0 +1,9 @@
+#include <cstdio>
+
+int main() {
+    const char* raw = R"delim(// not a comment /* nor this */)delim";
+    int big = 1'000'000;
+    char c = '\'';
+    printf("%s\n", "/* text */");
+    return 0;
+}
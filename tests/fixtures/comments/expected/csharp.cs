@@ -0,0 +1,2 @@
+var path = @"C:\dir\";
+var quoted = @"say ""// hi""";
@@ -0,0 +1,8 @@
+package main
+
+var raw = `raw // string
+/* still raw */`
+
+func main() {
+	println("// not", '/')
+}
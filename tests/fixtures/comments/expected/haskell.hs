@@ -0,0 +1,13 @@
+{-# LANGUAGE OverloadedStrings #-}
+module Main where
+
+(-->) :: Int -> Int -> Int
+a --> b = a + b
+
+main = print (foldl' (+) 0 [1 --> 2])
+
+greet = do
+  putStrLn "a"
+  print area
+  where
+    area = 1 * 2
@@ -0,0 +1,8 @@
+const url = "http://example.com";
+const re = /\/\/+|\/\*/g;
+const half = total / 2 / count;
+const tpl = `template // not a comment ${value + 1} /* kept */`;
+const nested = `outer ${`inner // text`} end`;
+function f(a) {
+  return a.replace(/#.*$/, "");
+}
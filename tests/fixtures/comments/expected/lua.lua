@@ -0,0 +1,3 @@
+local s = [[ -- a long string ]]
+local t = [==[ ]] -- still string ]==]
+print(s, t)
@@ -0,0 +1,8 @@
+<?php
+#[Attribute]
+class A {
+    public $s = "# not";
+    public $h = <<<EOT
+    // heredoc body
+    EOT;
+}
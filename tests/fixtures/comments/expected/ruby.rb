@@ -0,0 +1,5 @@
+name = "world # not a comment"
+puts "hello #{name}"
+text = <<~HEREDOC
+  # heredoc body
+HEREDOC
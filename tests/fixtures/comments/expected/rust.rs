@@ -0,0 +1,20 @@
+
+use std::fmt;
+
+fn parse<'a>(input: &'a str) -> &'a str {
+    let url = "https://example.com/*path*/";
+    let raw = r#"raw "string" with // slashes"#;
+    let bytes = br"/* bytes */";
+    let quote = '"';
+    let slash = '/';
+    let r#type = b'#';
+    let sum = 1 + 2;
+    let tight = 1 +2;
+    let indented = 1;
+    input
+}
+
+'outer: loop {
+    break 'outer;
+ let after = 1;
+}
@@ -0,0 +1,12 @@
+#!/bin/sh
+echo "count: $#"
+echo "${#ARRAY[@]}"
+echo 'single # quoted'
+url=http://example.com/#frag
+cat <<EOF2
+# inside heredoc
+EOF2
+cat <<-'END'
+	# also inside
+	END
+echo done;
@@ -0,0 +1,3 @@
+SELECT 'it''s -- not a comment', "col--name"
+FROM t
+WHERE a = 1;
@@ -0,0 +1,10 @@
+// Header comment
+#include <cstdio>
+
+int main() {
+    const char* raw = R"delim(// not a comment /* nor this */)delim";
+    int big = 1'000'000; // digit separators
+    char c = '\''; /* quote char */
+    printf("%s\n", "/* text */");
+    return 0;
+}
@@ -0,0 +1,3 @@
+// Comment
+var path = @"C:\dir\"; // trailing
+var quoted = @"say ""// hi""";
@@ -0,0 +1,9 @@
+package main
+
+// Comment
+var raw = `raw // string
+/* still raw */`
+
+func main() { /* block */
+	println("// not", '/') // end
+}
@@ -0,0 +1,15 @@
+{-# LANGUAGE OverloadedStrings #-}
+-- Comment
+{- outer {- nested -} done -}
+module Main where
+
+(-->) :: Int -> Int -> Int
+a --> b = a + b -- trailing
+
+main = print (foldl' (+) 0 [1 --> 2]) -- end
+
+greet = do
+  {- note -} putStrLn "a"
+  print area
+  where
+    {- c -} area = 1 * 2
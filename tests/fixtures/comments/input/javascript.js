@@ -0,0 +1,12 @@
+// Line comment
+const url = "http://example.com"; // trailing
+const re = /\/\/+|\/\*/g; // regex with slashes
+const half = total / 2 / count; /* division */
+const tpl = `template // not a comment ${value /* inner comment */ + 1} /* kept */`;
+const nested = `outer ${`inner // text`} end`;
+/**
+ * JSDoc block.
+ */
+function f(a) {
+  return a.replace(/#.*$/, ""); // strip
+}
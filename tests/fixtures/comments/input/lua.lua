@@ -0,0 +1,7 @@
+-- Comment
+--[[ long
+comment ]]
+local s = [[ -- a long string ]]
+local t = [==[ ]] -- still string ]==] -- trailing
+--[==[ level ]] two ]==]
+print(s, t)
@@ -0,0 +1,9 @@
+<?php
+# Hash comment
+#[Attribute]
+class A {
+    public $s = "# not"; // trailing
+    public $h = <<<EOT
+    // heredoc body
+    EOT;
+}
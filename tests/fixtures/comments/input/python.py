@@ -0,0 +1,16 @@
+#!/usr/bin/env python3
+# Module comment
+"""Module docstring with a # hash."""
+
+URL = "http://example.com/#anchor"  # trailing comment
+QUERY = 'it\'s # not a comment'
+
+
+def handler(x):
+    """Docstring.
+
+    # this line is part of the docstring
+    """
+    text = f"{x} # {x}"
+    raw = r"\d+#"
+    return x // 2  # integer division stays
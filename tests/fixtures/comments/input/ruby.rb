@@ -0,0 +1,9 @@
+# Comment
+=begin
+Block comment
+=end
+name = "world # not a comment"
+puts "hello #{name}" # trailing
+text = <<~HEREDOC
+  # heredoc body
+HEREDOC
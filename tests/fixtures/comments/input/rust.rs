@@ -0,0 +1,24 @@
+//! Crate docs.
+
+/* outer /* nested */ still a comment */
+use std::fmt; // trailing
+
+/// Doc comment.
+fn parse<'a>(input: &'a str) -> &'a str {
+    let url = "https://example.com/*path*/"; // a URL is not a comment
+    let raw = r#"raw "string" with // slashes"#;
+    let bytes = br"/* bytes */";
+    let quote = '"'; // a char literal holding a quote
+    let slash = '/';
+    let r#type = b'#';
+    let sum = 1 /* inline */ + 2;
+    let tight = 1/**/+2;
+    /* outer /* inner */ still */ let indented = 1;
+    input
+}
+
+'outer: loop {
+    break 'outer; /* multi
+    line
+    comment */ let after = 1;
+}
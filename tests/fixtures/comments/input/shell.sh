@@ -0,0 +1,13 @@
+#!/bin/sh
+# Script comment
+echo "count: $#" # args
+echo "${#ARRAY[@]}"
+echo 'single # quoted'
+url=http://example.com/#frag
+cat <<EOF2
+# inside heredoc
+EOF2
+cat <<-'END' # comment after heredoc marker
+	# also inside
+	END
+echo done;# after a semicolon
@@ -0,0 +1,4 @@
+-- Query comment
+SELECT 'it''s -- not a comment', "col--name" /* block */
+FROM t -- trailing
+WHERE a = 1;